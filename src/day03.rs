@@ -1,37 +1,133 @@
 use std::collections::HashMap;
+use std::fmt;
 
 type Pos = (usize, usize);
 type Digits = HashMap<Pos, char>;
 type Symbols = Vec<(usize, usize, char)>;
+type RatioFn = Box<dyn Fn(&[u64]) -> u64>;
 
-struct Sectors {
-    sec1: Option<String>,
-    sec2: Option<String>,
-    sec3: Option<String>,
-    sec4: Option<String>,
-    sec6: Option<String>,
-    sec7: Option<String>,
-    sec8: Option<String>,
-    sec9: Option<String>,
-}
-
-impl Sectors {
-    fn new() -> Self {
-        Sectors {
-            sec1: None, sec2: None, sec3: None,
-            sec4: None, sec6: None,
-            sec7: None, sec8: None, sec9: None,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Adjacency {
+    Eight,
+    Four,
+}
+
+impl Adjacency {
+    fn offsets(self) -> &'static [(isize, isize)] {
+        match self {
+            Adjacency::Eight => &[
+                (-1, -1), (0, -1), (1, -1),
+                (-1,  0),          (1,  0),
+                (-1,  1), (0,  1), (1,  1),
+            ],
+            Adjacency::Four  => &[(0, -1), (-1, 0), (1, 0), (0, 1)],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Count {
+    Exactly(usize),
+    AtLeast(usize),
+}
+
+impl Count {
+    fn accepts(self, n: usize) -> bool {
+        match self {
+            Count::Exactly(req) => n == req,
+            Count::AtLeast(req) => n >= req,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum GearError {
+    // The ratio at this symbol doesn't fit in a u64
+    Overflow(Pos),
+}
+
+impl fmt::Display for GearError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GearError::Overflow((col, row)) => write!(f, "Gear ratio at ({}, {}) is too large", col, row),
+        }
+    }
+}
+
+pub enum Combine {
+    Product,
+    Sum,
+    Custom(RatioFn),
+}
+
+impl Combine {
+    // None if the result doesn't fit
+    fn apply(&self, parts: &[u64]) -> Option<u64> {
+        match self {
+            Combine::Product      => parts.iter().try_fold(1_u64, |acc, &part| acc.checked_mul(part)),
+            Combine::Sum          => parts.iter().try_fold(0_u64, |acc, &part| acc.checked_add(part)),
+            Combine::Custom(func) => Some(func(parts)),
+        }
+    }
+}
+
+// Defaults to the puzzle's rule: a '*' with exactly two parts, multiplied together
+pub struct GearRule {
+    symbols: Vec<char>,
+    count: Count,
+    combine: Combine,
+    adjacency: Adjacency,
+}
+
+impl GearRule {
+    pub fn new(symbols: &str) -> Self {
+        GearRule {
+            symbols: symbols.chars().collect(),
+            count: Count::Exactly(2),
+            combine: Combine::Product,
+            adjacency: Adjacency::Eight,
+        }
+    }
+
+    pub fn count(mut self, count: Count) -> Self {
+        self.count = count;
+        self
+    }
+
+    pub fn combine(mut self, combine: Combine) -> Self {
+        self.combine = combine;
+        self
+    }
+
+    pub fn adjacency(mut self, adjacency: Adjacency) -> Self {
+        self.adjacency = adjacency;
+        self
+    }
+
+    // Returns the gear ratio if the symbol at `pos` is a gear under this rule. A gear can touch up
+    // to six parts of any length, so a ratio too big for a u64 is an error rather than a wrap.
+    pub fn ratio(&self, pos: Pos, symbol: char, parts: &[u64]) -> Result<Option<u64>, GearError> {
+        if self.symbols.contains(&symbol) && self.count.accepts(parts.len()) {
+            self.combine.apply(parts).map(Some).ok_or(GearError::Overflow(pos))
+        } else {
+            Ok(None)
         }
     }
 }
 
+impl Default for GearRule {
+    fn default() -> Self {
+        GearRule::new("*")
+    }
+}
+
 // I had planned to create a 2D map, but we don't need no stinkin' map
 #[aoc_generator(day3)]
 pub fn input_generator(input: &str) -> (Digits, Symbols) {
     let mut digits = HashMap::new();
     let mut symbols = Vec::new();
     for (row, line) in input.lines().enumerate() {
-        for (col, ch) in line.chars().enumerate() {
+        for (col, ch) in line.trim().chars().enumerate() {
             if ch.is_digit(10) {
                 digits.insert((col, row), ch);
             } else if ch != '.' {
@@ -43,140 +139,113 @@ pub fn input_generator(input: &str) -> (Digits, Symbols) {
     (digits, symbols)
 }
 
-// Returns a vector of part numbers by looking at adjacent positions around a symbol as such:
-// 1 2 3
-// 4 X 5
-// 6 7 8
-// There are no symbols at the edges of the map, so no need to generalise for out-of-bounds
-// This is awful and needs a rewrite.
-fn read_digits(pos: Pos, digits: &Digits) -> Option<Vec<u32>> {
-    let mut sectors = Sectors::new();
-    let mut parts: Vec<u32> = Vec::new();
-
-    // Scan by column then row
-    for col in 0..3 as usize {
-        for row in 0..3 as usize {
-            if (row, col) == (1, 1) {
-                continue;
-            }
-            if digits.contains_key(&(col + pos.0 - 1, row + pos.1 - 1)) {
-                let mut val = vec![digits.get(&(col + pos.0 - 1, row + pos.1 - 1)).unwrap()];
-                let mut n = 1;
-                if col == 0 {
-                    while digits.contains_key(&(col + pos.0 - 1 - n, row + pos.1 - 1)) {
-                        val.push(digits.get(&(col + pos.0 - 1 - n, row + pos.1 - 1)).unwrap());
-                        n += 1;
-                    }
-                    let val = val.into_iter().rev().collect();
-                    if row == 0 {
-                        sectors.sec1 = Some(val);
-                    } else if row == 1 {
-                        sectors.sec4 = Some(val);
-                    } else {
-                        sectors.sec7 = Some(val);
-                    }
-                } else if col == 2 {
-                    while digits.contains_key(&(col + pos.0 - 1 + n, row + pos.1 - 1)) {
-                        val.push(digits.get(&(col + pos.0 - 1 + n, row + pos.1 - 1)).unwrap());
-                        n += 1;
-                    }
-                    let val = val.into_iter().collect();
-                    if row == 0 {
-                        sectors.sec3 = Some(val);
-                    } else if row == 1 {
-                        sectors.sec6 = Some(val);
-                    } else {
-                        sectors.sec9 = Some(val);
-                    }
-                } else {
-                    let val = val.into_iter().collect();
-                    if row == 0 {
-                        sectors.sec2 = Some(val);
-                    } else {
-                        sectors.sec8 = Some(val);
-                    }
-                }
-            }
-        }
-    }
+// Returns the part numbers touching a symbol. A number is identified by the position of its
+// leftmost digit, so a number spanning several neighbouring cells is only read once.
+fn read_digits(pos: Pos, digits: &Digits, adjacency: Adjacency) -> Vec<u64> {
+    let mut heads: Vec<Pos> = Vec::new();
 
-    // Build list of parts and test for congruity
-    if sectors.sec2.is_none() {
-        if sectors.sec1.is_some() {
-            parts.push(sectors.sec1.unwrap().parse::<u32>().unwrap());
+    for &(dx, dy) in adjacency.offsets() {
+        let (Some(col), Some(row)) = (pos.0.checked_add_signed(dx), pos.1.checked_add_signed(dy)) else {
+            continue;
+        };
+        if !digits.contains_key(&(col, row)) {
+            continue;
         }
-        if sectors.sec3.is_some() {
-            parts.push(sectors.sec3.unwrap().parse::<u32>().unwrap());
+        let mut head = col;
+        while head > 0 && digits.contains_key(&(head - 1, row)) {
+            head -= 1;
         }
-    } else {
-        let mut cen = sectors.sec2.unwrap();
-        if sectors.sec1.is_some() {
-            cen = sectors.sec1.unwrap() + &cen;
+        if !heads.contains(&(head, row)) {
+            heads.push((head, row));
         }
-        if sectors.sec3.is_some() {
-            cen = cen + &sectors.sec3.unwrap();
-        }
-        parts.push(cen.parse::<u32>().unwrap())
-    }
-    if sectors.sec4.is_some() {
-        parts.push(sectors.sec4.unwrap().parse::<u32>().unwrap());
-    }
-    if sectors.sec6.is_some() {
-        parts.push(sectors.sec6.unwrap().parse::<u32>().unwrap());
-    }
-    if sectors.sec8.is_none() {
-        if sectors.sec7.is_some() {
-            parts.push(sectors.sec7.unwrap().parse::<u32>().unwrap());
-        }
-        if sectors.sec9.is_some() {
-            parts.push(sectors.sec9.unwrap().parse::<u32>().unwrap());
-        }
-    } else {
-        let mut cen = sectors.sec8.unwrap();
-        if sectors.sec7.is_some() {
-            cen = sectors.sec7.unwrap() + &cen;
-        }
-        if sectors.sec9.is_some() {
-            cen = cen + &sectors.sec9.unwrap();
-        }
-        parts.push(cen.parse::<u32>().unwrap())
     }
 
-    if parts.is_empty() {
-        None
-    } else {
-        Some(parts)
-    }
+    heads
+        .into_iter()
+        .map(|(mut col, row)| {
+            let mut val = 0;
+            while let Some(digit) = digits.get(&(col, row)) {
+                val = val * 10 + digit.to_digit(10).unwrap() as u64;
+                col += 1;
+            }
+            val
+        })
+        .collect()
+}
+
+pub fn sum_gears(input: &(Digits, Symbols), rule: &GearRule) -> Result<u64, GearError> {
+    input.1.iter().try_fold(0_u64, |total, &(col, row, symbol)| {
+        let parts = read_digits((col, row), &input.0, rule.adjacency);
+        let ratio = rule.ratio((col, row), symbol, &parts)?.unwrap_or(0);
+        total.checked_add(ratio).ok_or(GearError::Overflow((col, row)))
+    })
 }
 
 #[aoc(day3, part1)]
-pub fn solve_part1(input: &(Digits, Symbols)) -> u32 {
+pub fn solve_part1(input: &(Digits, Symbols)) -> u64 {
     let digits = &input.0;
     let symbols = &input.1;
-    let mut sum: u32 = 0;
+    let mut sum: u64 = 0;
 
     for symbol in symbols.iter() {
-        if let Some(parts) = read_digits((symbol.0, symbol.1), digits) {
-            sum += parts.iter().sum::<u32>();
-        }
+        sum += read_digits((symbol.0, symbol.1), digits, Adjacency::Eight).iter().sum::<u64>();
     }
 
     sum
 }
 
 #[aoc(day3, part2)]
-pub fn solve_part2(input: &(Digits, Symbols)) -> u32 {
-    let digits = &input.0;
-    let symbols = &input.1;
-    let mut sum: u32 = 0;
+pub fn solve_part2(input: &(Digits, Symbols)) -> u64 {
+    sum_gears(input, &GearRule::default()).unwrap_or_else(|err| panic!("{err}"))
+}
 
-    for symbol in symbols.iter().filter(|(_, _, s)| *s == '*') {
-        if let Some(parts) = read_digits((symbol.0, symbol.1), digits) {
-            if parts.len() == 2 {
-                sum += parts[0] * parts[1];
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST: &str = "467..114..
+                        ...*......
+                        ..35..633.
+                        ......#...
+                        617*......
+                        .....+.58.
+                        ..592.....
+                        ......755.
+                        ...$.*....
+                        .664.598..";
+
+    #[test]
+    fn part1() {
+        assert_eq!(solve_part1(&input_generator(TEST)), 4361);
     }
 
-    sum
-}
\ No newline at end of file
+    #[test]
+    fn part2() {
+        assert_eq!(solve_part2(&input_generator(TEST)), 467835);
+    }
+
+    #[test]
+    fn custom_rules() {
+        let input = input_generator(TEST);
+        let rule = GearRule::new("*#")
+            .count(Count::AtLeast(1))
+            .combine(Combine::Sum);
+        assert_eq!(sum_gears(&input, &rule), Ok(467 + 35 + 633 + 617 + 755 + 598));
+        let rule = GearRule::default().adjacency(Adjacency::Four);
+        assert_eq!(sum_gears(&input, &rule), Ok(0));
+        let rule = GearRule::new("*")
+            .count(Count::Exactly(1))
+            .combine(Combine::Custom(Box::new(|parts| parts[0] + 1)));
+        assert_eq!(sum_gears(&input, &rule), Ok(618));
+
+        let crowded = input_generator("999.999
+                                       999*999
+                                       999.999");
+        let rule = GearRule::default().count(Count::AtLeast(4));
+        assert_eq!(sum_gears(&crowded, &rule), Ok(999u64.pow(6)));
+
+        let long = input_generator("12345678901*12345678901");
+        assert_eq!(sum_gears(&long, &GearRule::default()), Err(GearError::Overflow((11, 0))));
+        assert_eq!(GearRule::default().combine(Combine::Sum).ratio((11, 0), '*', &[u64::MAX, 1]), Err(GearError::Overflow((11, 0))));
+    }
+}
@@ -23,6 +23,115 @@ impl Card {
 
         Card { eql }
     }
+
+    pub fn matches(&self) -> usize {
+        self.eql
+    }
+}
+
+// Decides which cards a card wins copies of. Each entry is a target card and the number of copies
+// of it won by a single instance of the winning card.
+pub trait CopyRule {
+    fn wins(&self, idx: usize, matches: usize, len: usize) -> Vec<(usize, u128)>;
+}
+
+// The puzzle's rule: one copy of each of the next `matches` cards, stopping at the last card
+pub struct Standard;
+
+impl CopyRule for Standard {
+    fn wins(&self, idx: usize, matches: usize, len: usize) -> Vec<(usize, u128)> {
+        (idx + 1..len.min(idx + 1 + matches)).map(|n| (n, 1)).collect()
+    }
+}
+
+// Like `Standard`, but every card in the window receives `matches` copies
+pub struct Proportional;
+
+impl CopyRule for Proportional {
+    fn wins(&self, idx: usize, matches: usize, len: usize) -> Vec<(usize, u128)> {
+        (idx + 1..len.min(idx + 1 + matches)).map(|n| (n, matches as u128)).collect()
+    }
+}
+
+// Like `Standard`, but never reaching more than the given number of cards ahead
+pub struct Capped(pub usize);
+
+impl CopyRule for Capped {
+    fn wins(&self, idx: usize, matches: usize, len: usize) -> Vec<(usize, u128)> {
+        Standard.wins(idx, matches.min(self.0), len)
+    }
+}
+
+// Like `Standard`, but the window continues from the first card instead of stopping at the last.
+// A card never wins copies of itself.
+pub struct WrapAround;
+
+impl CopyRule for WrapAround {
+    fn wins(&self, idx: usize, matches: usize, len: usize) -> Vec<(usize, u128)> {
+        (1..=matches.min(len - 1)).map(|n| ((idx + n) % len, 1)).collect()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CascadeError {
+    Overflow,
+    // The rule lets copies win copies of themselves, so the cascade never ends
+    Unbounded,
+}
+
+#[derive(Debug)]
+pub struct Cascade {
+    // Final number of instances of each card, originals included
+    pub copies: Vec<u128>,
+    // Length of the longest chain of cards winning copies of other cards
+    pub depth: usize,
+    // Cards that gave each card at least one copy
+    pub contributors: Vec<Vec<usize>>,
+    pub total: u128,
+}
+
+// Processes copies in waves: the originals are wave 0, and the copies won by wave n form wave n+1.
+// Any chain longer than the number of cards must revisit a card, so it would never terminate.
+pub fn simulate(cards: &[Card], rule: &impl CopyRule) -> Result<Cascade, CascadeError> {
+    let len = cards.len();
+    let mut copies = vec![1_u128; len];
+    let mut contributors = vec![Vec::new(); len];
+    let mut wave = vec![1_u128; len];
+    let mut depth = 0;
+
+    while wave.iter().any(|&count| count > 0) {
+        let mut next = vec![0_u128; len];
+        for (idx, card) in cards.iter().enumerate() {
+            if wave[idx] == 0 {
+                continue;
+            }
+            for (target, per) in rule.wins(idx, card.eql, len) {
+                let won = wave[idx].checked_mul(per).ok_or(CascadeError::Overflow)?;
+                if won == 0 {
+                    continue;
+                }
+                next[target] = next[target].checked_add(won).ok_or(CascadeError::Overflow)?;
+                copies[target] = copies[target].checked_add(won).ok_or(CascadeError::Overflow)?;
+                if !contributors[target].contains(&idx) {
+                    contributors[target].push(idx);
+                }
+            }
+        }
+        if next.iter().any(|&count| count > 0) {
+            depth += 1;
+            if depth >= len {
+                return Err(CascadeError::Unbounded);
+            }
+        }
+        wave = next;
+    }
+
+    let total = copies
+        .iter()
+        .try_fold(0_u128, |acc, &count| acc.checked_add(count))
+        .ok_or(CascadeError::Overflow)?;
+
+    Ok(Cascade { copies, depth, contributors, total })
 }
 
 #[aoc_generator(day4)]
@@ -44,21 +153,42 @@ pub fn solve_part1(input: &[Card]) -> usize {
 }
 
 #[aoc(day4, part2)]
-pub fn solve_part2(input: &[Card]) -> usize {
-    let mut copies = vec![1_usize; input.len()];
-    let len = input.len() - 1;
-
-    for (idx, card) in input.iter().enumerate() {
-        let win = if card.eql > len {
-            len
-        } else {
-            card.eql + idx
-        };
-
-        for n in idx + 1..=win {
-            copies[n] += copies[idx];
-        }
+pub fn solve_part2(input: &[Card]) -> u128 {
+    simulate(input, &Standard).unwrap().total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST: &str = "Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53
+                        Card 2: 13 32 20 16 61 | 61 30 68 82 17 32 24 19
+                        Card 3:  1 21 53 59 44 | 69 82 63 72 16 21 14  1
+                        Card 4: 41 92 73 84 69 | 59 84 76 51 58  5 54 83
+                        Card 5: 87 83 26 28 32 | 88 30 70 12 93 22 82 36
+                        Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11";
+
+    #[test]
+    fn part1() {
+        assert_eq!(solve_part1(&input_generator(TEST)), 13);
+    }
+
+    #[test]
+    fn part2() {
+        assert_eq!(solve_part2(&input_generator(TEST)), 30);
     }
 
-    copies.iter().sum()
-}
\ No newline at end of file
+    #[test]
+    fn cascade() {
+        let cards = input_generator(TEST);
+        let cascade = simulate(&cards, &Standard).unwrap();
+        assert_eq!(cascade.copies, vec![1, 2, 4, 8, 14, 1]);
+        assert_eq!(cascade.depth, 4);
+        assert_eq!(cascade.contributors[4], vec![0, 2, 3]);
+        assert!(cascade.contributors[5].is_empty());
+        assert_eq!(simulate(&cards, &Capped(1)).unwrap().total, 1 + 2 + 3 + 4 + 5 + 1);
+        assert_eq!(simulate(&cards, &WrapAround).unwrap().total, 30);
+        let cyclic = input_generator("Card 1: 1 | 1\nCard 2: 2 | 2");
+        assert_eq!(simulate(&cyclic, &WrapAround).unwrap_err(), CascadeError::Unbounded);
+    }
+}
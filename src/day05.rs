@@ -1,100 +1,395 @@
-use rayon::prelude::*;
+use std::{collections::HashMap, fmt};
 
-// Vec<usize> is just a list of all seed numbers
-// Vec<Vec<Map>> is a vector of all maps in the order of seed2dirt, dirt2shit, shit2aqua, aqua2lux, lux2temp, temp2damp, damp2loc
-// Would Vec<Vec<Vec<usize>>> be taking the piss just a little?
-// On second thought, let's take the piss.
-#[aoc_generator(day5)]
-pub fn input_generator(input: &str) -> (Vec<usize>, Vec<Vec<Vec<usize>>>) {
-    input
-        .split_once("\n\n")
-        .map(|(first, second)|
-            (first
-                .split(':')
-                .last()
-                .unwrap()
-                .split_whitespace()
-                .map(|seed| seed.parse().unwrap())
-                .collect(),
-            second
-                .split("\n\n")
-                .map(|maps| {
-                    maps.lines()
-                        .skip(1)
-                        .map(|map| {
-                            map
-                                .split_whitespace()
-                                .map(|num| num.parse().unwrap())
-                                .collect()
-                        })
-                        .collect()
-                })
-                .collect()))
-        .unwrap()
+// One [dest, src, len] line of a category map
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mapping {
+    pub dst: usize,
+    pub src: usize,
+    pub len: usize,
 }
 
-#[aoc(day5, part1)]
-pub fn solve_part1(input: &(Vec<usize>, Vec<Vec<Vec<usize>>>)) -> usize {
-    let seeds = &input.0;
-    let cats = &input.1;
+impl Mapping {
+    fn parse(line: &str) -> Option<Mapping> {
+        let nums: Vec<usize> = line
+            .split_whitespace()
+            .map(|num| num.parse().ok())
+            .collect::<Option<_>>()?;
 
-    let locs: Vec<usize> = seeds
-        .iter()
-        .map(|&seed| {
-            let mut result = seed;
-            for maps in cats {
-                for map in maps {
-                    if result >= map[1] && result < map[1] + map[2] {
-                        result = map[0] + result - map[1];
-                        break; // Second test case revealed a dirty, dirty trap
+        match nums[..] {
+            [dst, src, len] => Some(Mapping { dst, src, len }),
+            _ => None,
+        }
+    }
+
+    // Values stop at usize::MAX, so any part of a line that runs past it is cut off
+    fn clamp(&self) -> Mapping {
+        Mapping { len: self.len.min(usize::MAX - self.src).min(usize::MAX - self.dst), ..*self }
+    }
+
+    fn source(&self) -> Interval {
+        let clamped = self.clamp();
+        (clamped.src, clamped.src + clamped.len)
+    }
+}
+
+// A category map is keyed by its (from, to) categories, e.g. ("seed", "soil")
+pub type Key = (String, String);
+
+#[derive(Debug, PartialEq)]
+pub enum AlmanacError {
+    BadSeeds(String),
+    BadHeader(String),
+    BadMapping(String),
+    Duplicate(String),
+    Missing { from: String, to: String },
+    Cycle(String),
+    OutOfOrder { expected: String, found: String },
+}
+
+impl fmt::Display for AlmanacError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AlmanacError::BadSeeds(line)   => write!(f, "expected \"seeds: <seed> <seed> ...\", found \"{line}\""),
+            AlmanacError::BadHeader(line)  => write!(f, "expected a \"<from>-to-<to> map:\" header, found \"{line}\""),
+            AlmanacError::BadMapping(line) => write!(f, "expected \"<dest> <src> <len>\", found \"{line}\""),
+            AlmanacError::Duplicate(cat)   => write!(f, "more than one map converts from {cat}"),
+            AlmanacError::Missing { from, to } => write!(f, "no map converts from {from}, so {to} can't be reached"),
+            AlmanacError::Cycle(cat)       => write!(f, "the maps loop back round to {cat}"),
+            AlmanacError::OutOfOrder { expected, found } => {
+                write!(f, "expected a map from {expected}, found one from {found}")
+            },
+        }
+    }
+}
+
+// Two lines of the same map claiming some of the same source values
+#[derive(Debug, PartialEq)]
+pub struct Overlap {
+    pub map: Key,
+    pub first: usize,
+    pub second: usize,
+    pub range: Interval,
+}
+
+#[derive(Debug)]
+pub struct Almanac {
+    pub seeds: Vec<usize>,
+    pub maps: HashMap<Key, Vec<Mapping>>,
+    // Keys in the order their maps appear in the input
+    pub order: Vec<Key>,
+}
+
+impl Almanac {
+    // Maps may be listed in any order here, but each category can only be converted one way.
+    // The puzzle input is held to the order from seed to location by `check_order`.
+    pub fn parse(input: &str) -> Result<Almanac, AlmanacError> {
+        let (first, second) = input.split_once("\n\n").unwrap_or((input, ""));
+        let seeds = first
+            .split_once(':')
+            .ok_or_else(|| AlmanacError::BadSeeds(first.trim().to_string()))?
+            .1
+            .split_whitespace()
+            .map(|seed| seed.parse().map_err(|_| AlmanacError::BadSeeds(first.trim().to_string())))
+            .collect::<Result<_, _>>()?;
+        let mut maps = HashMap::new();
+        let mut order: Vec<Key> = Vec::new();
+
+        for block in second.split("\n\n").filter(|block| !block.trim().is_empty()) {
+            let mut lines = block.lines().map(str::trim);
+            let header = lines.next().unwrap();
+            let key = header
+                .strip_suffix(" map:")
+                .and_then(|name| name.split_once("-to-"))
+                .map(|(from, to)| (from.to_string(), to.to_string()))
+                .ok_or_else(|| AlmanacError::BadHeader(header.to_string()))?;
+            if order.iter().any(|(from, _)| *from == key.0) {
+                return Err(AlmanacError::Duplicate(key.0));
+            }
+            let mappings = lines
+                .map(|line| Mapping::parse(line).ok_or_else(|| AlmanacError::BadMapping(line.to_string())))
+                .collect::<Result<_, _>>()?;
+            order.push(key.clone());
+            maps.insert(key, mappings);
+        }
+
+        Ok(Almanac { seeds, maps, order })
+    }
+
+    // Follows the maps from one category to another, returning the keys used along the way
+    pub fn path(&self, from: &str, to: &str) -> Result<Vec<&Key>, AlmanacError> {
+        let mut path: Vec<&Key> = Vec::new();
+        let mut current = from;
+        while current != to {
+            let key = self.order
+                .iter()
+                .find(|(src, _)| src == current)
+                .ok_or_else(|| AlmanacError::Missing { from: current.to_string(), to: to.to_string() })?;
+            if key.1 == from || path.iter().any(|(src, _)| *src == key.1) {
+                return Err(AlmanacError::Cycle(key.1.clone()));
+            }
+            path.push(key);
+            current = &key.1;
+        }
+
+        Ok(path)
+    }
+
+    // Checks the maps are listed as one unbroken chain running from seed to location
+    pub fn check_order(&self) -> Result<(), AlmanacError> {
+        let mut expected = "seed";
+        for (from, to) in &self.order {
+            if from != expected {
+                return Err(AlmanacError::OutOfOrder { expected: expected.to_string(), found: from.clone() });
+            }
+            expected = to;
+        }
+        if expected != "location" {
+            return Err(AlmanacError::Missing { from: expected.to_string(), to: "location".to_string() });
+        }
+
+        Ok(())
+    }
+
+    // Composes the maps along the path between two categories into a single map
+    pub fn compose(&self, from: &str, to: &str) -> Result<RangeMap, AlmanacError> {
+        Ok(self.path(from, to)?
+            .into_iter()
+            .fold(RangeMap::identity(), |acc, key| acc.compose(&RangeMap::new(&self.maps[key]))))
+    }
+
+    pub fn compose_all(&self) -> RangeMap {
+        self.compose("seed", "location").unwrap()
+    }
+
+    pub fn source_overlaps(&self) -> Vec<Overlap> {
+        let mut overlaps = Vec::new();
+        for key in &self.order {
+            let mappings = &self.maps[key];
+            for (first, a) in mappings.iter().enumerate() {
+                for (second, b) in mappings.iter().enumerate().skip(first + 1) {
+                    if let Some(range) = intersect(a.source(), b.source()) {
+                        overlaps.push(Overlap { map: key.clone(), first, second, range });
                     }
                 }
             }
-            result
-        })
-        .collect();
+        }
+
+        overlaps
+    }
 
-    *locs.iter().min().unwrap()
+    // Lists the category maps that send more than one value to the same place
+    pub fn non_bijective(&self) -> Vec<&Key> {
+        self.order
+            .iter()
+            .filter(|key| !RangeMap::new(&self.maps[*key]).is_bijection())
+            .collect()
+    }
 }
 
-#[aoc(day5, part2)]
-pub fn solve_part2(input: &(Vec<usize>, Vec<Vec<Vec<usize>>>)) -> usize {
-    // Run out of memory doing it this way.
-    // Maybe I'll try it on my desktop, but I'll split it for my laptop.
-    // let seeds: Vec<usize> = input.0
-    //     .as_slice()
-    //     .chunks_exact(2)
-    //     .flat_map(|ch| (ch[0]..ch[0]+ch[1]))
-    //     .collect();
-    let seeds = &input.0;
-    let cats = &input.1;
-
-    seeds
-        .chunks_exact(2)
-        .map(|ch| {
-            let batch = (ch[0]..ch[0] + ch[1]).collect::<Vec<usize>>();
-            let locs: Vec<usize> = batch
-                .par_iter()
-                .map(|&seed| {
-                    let mut result = seed;
-                    for maps in cats {
-                        for map in maps {
-                            if result >= map[1] && result < map[1] + map[2] {
-                                result = map[0] + result - map[1];
-                                break;
-                            }
-                        }
-                    }
-                    result
-                })
-                .collect();
+fn intersect(a: Interval, b: Interval) -> Option<Interval> {
+    let (lo, hi) = (a.0.max(b.0), a.1.min(b.1));
+    if lo < hi {
+        Some((lo, hi))
+    } else {
+        None
+    }
+}
+
+#[aoc_generator(day5)]
+pub fn input_generator(input: &str) -> Almanac {
+    let almanac = Almanac::parse(input).unwrap_or_else(|err| panic!("{err}"));
+    if let Err(err) = almanac.check_order() {
+        panic!("{err}");
+    }
+
+    almanac
+}
+
+// Half-open interval [start, end)
+pub type Interval = (usize, usize);
+
+// A run of source values [src, end) mapped linearly onto [dst, dst + end - src)
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Segment {
+    src: usize,
+    end: usize,
+    dst: usize,
+}
+
+impl Segment {
+    fn get(&self, val: usize) -> usize {
+        self.dst + (val - self.src)
+    }
+}
+
+// Piecewise-linear map built from the almanac's [dest, src, len] mappings. Segments are sorted
+// and partition the whole of [0, usize::MAX), with unmapped values filled in as identity runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeMap {
+    segments: Vec<Segment>,
+}
+
+impl RangeMap {
+    // Where source ranges overlap, the first mapping listed wins
+    pub fn new(mappings: &[Mapping]) -> Self {
+        let mappings: Vec<Mapping> = mappings.iter().map(Mapping::clamp).collect();
+        let mut bounds: Vec<usize> = mappings
+            .iter()
+            .flat_map(|m| [m.src, m.src + m.len])
+            .chain([0, usize::MAX])
+            .collect();
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        let segments = bounds
+            .windows(2)
+            .map(|win| {
+                let dst = mappings
+                    .iter()
+                    .find(|m| m.src <= win[0] && win[0] < m.src + m.len)
+                    .map_or(win[0], |m| m.dst + (win[0] - m.src));
+                Segment { src: win[0], end: win[1], dst }
+            })
+            .collect();
+
+        RangeMap::from_segments(segments)
+    }
 
-            *locs.iter().min().unwrap()
+    pub fn identity() -> Self {
+        RangeMap { segments: vec![Segment { src: 0, end: usize::MAX, dst: 0 }] }
+    }
+
+    // Merges neighbouring segments that continue the same linear run
+    fn from_segments(segments: Vec<Segment>) -> Self {
+        let mut merged: Vec<Segment> = Vec::with_capacity(segments.len());
+        for seg in segments.into_iter().filter(|seg| seg.src < seg.end) {
+            match merged.last_mut() {
+                Some(last) if last.end == seg.src && last.get(last.src) + (seg.src - last.src) == seg.dst => {
+                    last.end = seg.end;
+                }
+                _ => merged.push(seg),
+            }
+        }
+
+        RangeMap { segments: merged }
+    }
+
+    fn segment(&self, val: usize) -> Option<&Segment> {
+        let idx = self.segments.partition_point(|seg| seg.end <= val);
+        self.segments.get(idx)
+    }
+
+    // usize::MAX is the one value past the end of the segments, and it's left as it is
+    pub fn get(&self, val: usize) -> usize {
+        self.segment(val).map_or(val, |seg| seg.get(val))
+    }
+
+    // Maps a whole interval, splitting it wherever it crosses a segment boundary. An empty
+    // interval has nothing to map, so it gives back no pieces at all.
+    pub fn map_interval(&self, (start, end): Interval) -> Vec<Interval> {
+        let first = self.segments.partition_point(|seg| seg.end <= start);
+        self.segments[first..]
+            .iter()
+            .take_while(|seg| seg.src < end)
+            .filter_map(|seg| {
+                let (lo, hi) = intersect((start, end), (seg.src, seg.end))?;
+                Some((seg.get(lo), seg.get(lo) + (hi - lo)))
+            })
+            .collect()
+    }
+
+    pub fn map_intervals(&self, intervals: &[Interval]) -> Vec<Interval> {
+        intervals
+            .iter()
+            .flat_map(|&interval| self.map_interval(interval))
+            .collect()
+    }
+
+    // Returns the map equivalent to applying `self` and then `next`
+    pub fn compose(&self, next: &RangeMap) -> RangeMap {
+        let mut segments = Vec::new();
+        for seg in &self.segments {
+            let mut src = seg.src;
+            for (lo, hi) in next.map_interval((seg.dst, seg.get(seg.end - 1) + 1)) {
+                segments.push(Segment { src, end: src + (hi - lo), dst: lo });
+                src += hi - lo;
+            }
+        }
+
+        RangeMap::from_segments(segments)
+    }
+
+    // Returns every source interval that lands inside the target interval, sorted and merged
+    pub fn preimage(&self, target: Interval) -> Vec<Interval> {
+        let mut sources: Vec<Interval> = self.segments
+            .iter()
+            .filter_map(|seg| {
+                let (lo, hi) = intersect((seg.dst, seg.get(seg.end - 1) + 1), target)?;
+                Some((seg.src + (lo - seg.dst), seg.src + (hi - seg.dst)))
+            })
+            .collect();
+        sources.sort_unstable();
+
+        sources.into_iter().fold(Vec::new(), |mut merged: Vec<Interval>, (lo, hi)| {
+            match merged.last_mut() {
+                Some(last) if last.1 >= lo => last.1 = last.1.max(hi),
+                _ => merged.push((lo, hi)),
+            }
+            merged
         })
+    }
+
+    // Every source value mapping to `val`; a bijection always gives exactly one
+    pub fn inverse(&self, val: usize) -> Vec<usize> {
+        let Some(next) = val.checked_add(1) else {
+            return vec![val];
+        };
+        self.preimage((val, next))
+            .into_iter()
+            .flat_map(|(lo, hi)| lo..hi)
+            .collect()
+    }
+
+    // The segments partition the domain, so the map is a bijection exactly when no two
+    // segments share any part of their images
+    pub fn is_bijection(&self) -> bool {
+        let mut images: Vec<Interval> = self.segments
+            .iter()
+            .map(|seg| (seg.dst, seg.get(seg.end - 1) + 1))
+            .collect();
+        images.sort_unstable();
+
+        images.windows(2).all(|win| win[0].1 <= win[1].0)
+    }
+}
+
+pub fn min_start(intervals: &[Interval]) -> Option<usize> {
+    intervals.iter().filter(|&&(start, end)| start < end).map(|&(start, _)| start).min()
+}
+
+#[aoc(day5, part1)]
+pub fn solve_part1(input: &Almanac) -> usize {
+    let almanac = input.compose_all();
+
+    input.seeds
+        .iter()
+        .map(|&seed| almanac.get(seed))
         .min()
         .unwrap()
 }
 
+// This used to brute force every seed with rayon and took 104s; mapping whole intervals through
+// the composed almanac does away with that.
+#[aoc(day5, part2)]
+pub fn solve_part2(input: &Almanac) -> usize {
+    let seeds: Vec<Interval> = input.seeds
+        .chunks_exact(2)
+        .map(|ch| (ch[0], ch[0].saturating_add(ch[1])))
+        .collect();
+
+    min_start(&input.compose_all().map_intervals(&seeds)).unwrap()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -142,4 +437,98 @@ mod tests {
     fn part2() {
         assert_eq!(solve_part2(&input_generator(TEST)), 46);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn range_map() {
+        let input = input_generator(TEST);
+        let soil = input.compose("seed", "soil").unwrap();
+        assert_eq!(soil.map_interval((45, 100)), vec![(45, 50), (52, 100), (50, 52)]);
+        assert_eq!(soil.map_interval((100, 110)), vec![(100, 110)]);
+
+        let almanac = input.compose_all();
+        for seed in 0..120 {
+            let stepwise = input.order
+                .iter()
+                .fold(seed, |val, key| RangeMap::new(&input.maps[key]).get(val));
+            assert_eq!(almanac.get(seed), stepwise);
+        }
+    }
+
+    #[test]
+    fn inverse() {
+        let input = input_generator(TEST);
+        let almanac = input.compose_all();
+        assert!(almanac.is_bijection());
+        assert!(input.non_bijective().is_empty());
+        assert!(input.source_overlaps().is_empty());
+        assert_eq!(almanac.inverse(46), vec![82]);
+        for (lo, hi) in almanac.preimage((40, 50)) {
+            assert!((lo..hi).all(|seed| (40..50).contains(&almanac.get(seed))));
+        }
+
+        let squashed = RangeMap::new(&[Mapping { dst: 0, src: 10, len: 5 }, Mapping { dst: 3, src: 12, len: 5 }]);
+        assert!(!squashed.is_bijection());
+        assert_eq!(squashed.inverse(2), vec![2, 12]);
+        assert_eq!(squashed.get(usize::MAX), usize::MAX);
+        assert_eq!(squashed.inverse(usize::MAX), vec![usize::MAX]);
+    }
+
+    #[test]
+    fn empty_intervals() {
+        let map = RangeMap::new(&[Mapping { dst: 0, src: 10, len: 5 }]);
+        assert!(map.map_interval((12, 12)).is_empty());
+        assert_eq!(map.map_interval((12, 20)), vec![(2, 5), (15, 20)]);
+        assert_eq!(min_start(&[(2, 2), (7, 9)]), Some(7));
+        assert_eq!(min_start(&[(2, 2)]), None);
+    }
+
+    #[test]
+    fn top_of_range() {
+        let high = usize::MAX - 5;
+        let map = RangeMap::new(&[Mapping { dst: 0, src: high, len: 100 }, Mapping { dst: high, src: 0, len: 100 }]);
+        assert_eq!(map.get(usize::MAX - 1), 4);
+        assert_eq!(map.get(4), usize::MAX - 1);
+        assert_eq!(map.get(5), 5);
+        assert!(map.is_bijection());
+
+        let input = input_generator(&format!("seeds: {high} 100 10 1\n\nseed-to-location map:\n0 {high} 100"));
+        assert_eq!(input.compose_all().get(high), 0);
+        assert_eq!(solve_part1(&input), 0);
+        assert_eq!(solve_part2(&input), 0);
+    }
+
+    #[test]
+    fn categories() {
+        let input = input_generator(TEST);
+        assert!(input.check_order().is_ok());
+        assert_eq!(input.path("soil", "humidity").unwrap().len(), 5);
+        assert_eq!(input.compose("soil", "soil").unwrap(), RangeMap::identity());
+        assert_eq!(
+            input.path("location", "seed").unwrap_err(),
+            AlmanacError::Missing { from: "location".to_string(), to: "seed".to_string() }
+        );
+
+        let (head, tail) = TEST.split_once("\n\n").unwrap();
+        let (soil, rest) = tail.split_once("\n\n").unwrap();
+        let shuffled = Almanac::parse(&format!("{head}\n\n{rest}\n\n{soil}")).unwrap();
+        assert_eq!(shuffled.compose_all(), input.compose_all());
+        assert_eq!(
+            shuffled.check_order().unwrap_err(),
+            AlmanacError::OutOfOrder { expected: "seed".to_string(), found: "soil".to_string() }
+        );
+
+        let missing = Almanac::parse(&format!("{head}\n\n{rest}")).unwrap();
+        assert!(missing.path("seed", "location").is_err());
+        assert!(matches!(Almanac::parse(&format!("{head}\n\nseeds to soil:\n1 2 3")), Err(AlmanacError::BadHeader(_))));
+        assert_eq!(Almanac::parse("seeds: 1 x").unwrap_err(), AlmanacError::BadSeeds("seeds: 1 x".to_string()));
+        assert!(matches!(Almanac::parse("1 2 3"), Err(AlmanacError::BadSeeds(_))));
+    }
+
+    #[test]
+    #[should_panic(expected = "expected a map from seed, found one from soil")]
+    fn out_of_order() {
+        let (head, tail) = TEST.split_once("\n\n").unwrap();
+        let (soil, rest) = tail.split_once("\n\n").unwrap();
+        input_generator(&format!("{head}\n\n{rest}\n\n{soil}"));
+    }
+}
@@ -1,4 +1,4 @@
-fn parse_num(input: &str) -> Vec<usize> {
+fn parse_num(input: &str) -> Vec<u128> {
     input
         .split_whitespace()
         .skip(1)
@@ -6,7 +6,7 @@ fn parse_num(input: &str) -> Vec<usize> {
         .collect()
 }
 
-fn parse_line(input: &str) -> usize {
+fn parse_line(input: &str) -> u128 {
     input
         .split_whitespace()
         .skip(1)
@@ -15,8 +15,219 @@ fn parse_line(input: &str) -> usize {
         .unwrap()
 }
 
+// Winning hold times [lo, hi]
+pub type Window = (u128, u128);
+
+// Newton's method, since u128::isqrt needs a newer compiler than we target
+fn isqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+    let mut x = 1_u128 << ((128 - n.leading_zeros()).div_ceil(2));
+    loop {
+        let y = (x + n / x) / 2;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
+
+// Returns the range of hold times [lo, hi] that beat the record, if there are any.
+// Writing T = 2h + p with p the parity, holding for t = h - x travels h(h + p) - x(x + p), so the
+// boat wins while x(x + p) is below the spare distance h(h + p) - D. The integer square root of
+// that lands within a step of the largest such x. Nothing here squares T itself, and when even
+// h(h + p) doesn't fit, the peak beats any record and a binary search finds the first win.
+pub fn winning_interval(time: u128, dist: u128) -> Option<Window> {
+    let (half, odd) = (time / 2, time % 2);
+    let lo = match half.checked_mul(half + odd) {
+        Some(best) => {
+            let spare = best.checked_sub(dist).filter(|&spare| spare > 0)?;
+            let mut x = isqrt(spare);
+            while x * (x + odd) >= spare {
+                x -= 1;
+            }
+            half - x
+        }
+        None => search(0, half, |t| (time - t).checked_mul(t).map_or(true, |d| d > dist)),
+    };
+
+    Some((lo, time - lo))
+}
+
+pub fn ways(time: u128, dist: u128) -> u128 {
+    winning_interval(time, dist).map_or(0, |(lo, hi)| hi - lo + 1)
+}
+
+// A race where holding the button for `hold` of the `time` ms sends the boat `distance` mm.
+// Every model here is unimodal in the hold time (it rises to a peak and then falls away), so the
+// winning hold times always form a single interval. Distances are signed so that models with drag
+// can go negative rather than flattening out at zero, which would break the searches.
+pub trait RaceModel {
+    fn distance(&self, time: u128, hold: u128) -> i128;
+
+    // Closed-form solution, for models that have one
+    fn analytic(&self, _time: u128, _dist: u128) -> Option<Option<Window>> {
+        None
+    }
+
+    // Binary search for the peak, then for the record on either side of it
+    fn numeric(&self, time: u128, dist: u128) -> Option<Window> {
+        let beats = |hold: u128| self.distance(time, hold) > dist as i128;
+        let peak = search(0, time, |hold| self.distance(time, hold + 1) <= self.distance(time, hold));
+        if !beats(peak) {
+            return None;
+        }
+        let lo = search(0, peak, beats);
+        let hi = search(peak, time + 1, |hold| !beats(hold)) - 1;
+
+        Some((lo, hi))
+    }
+
+    fn winning(&self, time: u128, dist: u128) -> Option<Window> {
+        self.analytic(time, dist).unwrap_or_else(|| self.numeric(time, dist))
+    }
+}
+
+// First value in [lo, hi) for which the predicate holds, or `hi` if it never does.
+// The predicate has to be false and then true.
+fn search(mut lo: u128, mut hi: u128, pred: impl Fn(u128) -> bool) -> u128 {
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if pred(mid) {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+
+    lo
+}
+
+// Returns the shared answer, or both answers (analytic first) if the two methods disagree
+pub fn cross_check(
+    model: &impl RaceModel,
+    time: u128,
+    dist: u128,
+) -> Result<Option<Window>, (Option<Window>, Option<Window>)> {
+    let numeric = model.numeric(time, dist);
+    match model.analytic(time, dist) {
+        Some(analytic) if analytic != numeric => Err((analytic, numeric)),
+        _ => Ok(numeric),
+    }
+}
+
+// Speed goes up by `rate` mm/ms for every ms the button is held. Rate 1 is the puzzle.
+pub struct Linear(pub u128);
+
+impl RaceModel for Linear {
+    fn distance(&self, time: u128, hold: u128) -> i128 {
+        (self.0 * hold * (time - hold)) as i128
+    }
+
+    // rate·t(T - t) > D exactly when t(T - t) > ⌊D / rate⌋, as t(T - t) is a whole number
+    fn analytic(&self, time: u128, dist: u128) -> Option<Option<Window>> {
+        if self.0 == 0 {
+            return Some(None);
+        }
+        Some(winning_interval(time, dist / self.0))
+    }
+}
+
+// Speed grows with the square of the hold time, giving a cubic with no tidy integer solution
+pub struct AccelSquared(pub u128);
+
+impl RaceModel for AccelSquared {
+    fn distance(&self, time: u128, hold: u128) -> i128 {
+        (self.0 * hold * hold * (time - hold)) as i128
+    }
+}
+
+// Like `Linear`, but the boat stops charging once the button has been held for `cap` ms
+pub struct Capped {
+    pub rate: u128,
+    pub cap: u128,
+}
+
+impl RaceModel for Capped {
+    fn distance(&self, time: u128, hold: u128) -> i128 {
+        (self.rate * hold.min(self.cap) * (time - hold)) as i128
+    }
+
+    // Up to the cap this is the linear model. Past it the speed is fixed at rate·cap, so the boat
+    // wins while the time left exceeds ⌊D / (rate·cap)⌋.
+    fn analytic(&self, time: u128, dist: u128) -> Option<Option<Window>> {
+        let head = Linear(self.rate)
+            .analytic(time, dist)?
+            .and_then(|(lo, hi)| (lo <= self.cap).then_some((lo, hi.min(self.cap))));
+        let speed = self.rate * self.cap;
+        let tail = (speed > 0)
+            .then(|| (time + 1).checked_sub(dist / speed + 2))
+            .flatten()
+            .and_then(|hi| (hi > self.cap).then_some((self.cap + 1, hi)));
+
+        Some(match (head, tail) {
+            (Some((lo, _)), Some((_, hi))) => Some((lo, hi)),
+            (head, tail) => head.or(tail),
+        })
+    }
+}
+
+// Like `Linear`, but drag costs `drag` mm for every ms squared the boat is moving
+pub struct Drag {
+    pub rate: u128,
+    pub drag: u128,
+}
+
+impl RaceModel for Drag {
+    fn distance(&self, time: u128, hold: u128) -> i128 {
+        let moving = (time - hold) as i128;
+        (self.rate * hold) as i128 * moving - self.drag as i128 * moving * moving
+    }
+
+    // With m = T - t ms spent moving, the boat goes m(rate·T - (rate + drag)·m), a quadratic in m
+    // with roots at (B ± √(B² - 4aD)) / 2a for B = rate·T and a = rate + drag. As with the plain
+    // race, the integer square root gets within a step of each end. Falls back on the numeric
+    // search if B² doesn't fit.
+    fn analytic(&self, time: u128, dist: u128) -> Option<Option<Window>> {
+        let a = self.rate.checked_add(self.drag)?;
+        let b = self.rate.checked_mul(time)?;
+        if a == 0 {
+            return Some(None);
+        }
+        let wins = |m: u128| {
+            a.checked_mul(m)
+                .filter(|&am| am < b)
+                .is_some_and(|am| (b - am).checked_mul(m).map_or(true, |d| d > dist))
+        };
+        let Some(disc) = b.checked_mul(b)?.checked_sub(a.checked_mul(4)?.checked_mul(dist)?) else {
+            return Some(None);
+        };
+        let root = isqrt(disc);
+        let mut lo = (b - root) / (2 * a);
+        let mut hi = (b + root) / (2 * a);
+        while lo > 0 && wins(lo - 1) {
+            lo -= 1;
+        }
+        while lo <= hi && !wins(lo) {
+            lo += 1;
+        }
+        if lo > hi {
+            return Some(None);
+        }
+        while wins(hi + 1) {
+            hi += 1;
+        }
+        while !wins(hi) {
+            hi -= 1;
+        }
+
+        Some(Some((time - hi, time - lo)))
+    }
+}
+
 #[aoc(day6, part1)]
-pub fn part1(input: &str) -> usize {
+pub fn part1(input: &str) -> u128 {
     let (times, dists) = input
         .split_once("\n")
         .map(|(first, second)| (parse_num(first), parse_num(second)))
@@ -25,33 +236,106 @@ pub fn part1(input: &str) -> usize {
     times
         .iter()
         .zip(&dists)
-        .map(|(&time, &dist)| {
-            (1..time)
-                .filter(|&t| (time - t) * t > dist)
-                .count()
-        })
+        .map(|(&time, &dist)| ways(time, dist))
         .product()
 }
 
 #[aoc(day6, part2)]
-pub fn part2(input: &str) -> usize {
+pub fn part2(input: &str) -> u128 {
     let (time, dist) = input
         .split_once("\n")
         .map(|(first, second)| (parse_line(first), parse_line(second)))
         .unwrap();
     
-    (1..time)
-        .filter(|t| (time - t) * t > dist)
-        .count()
+    ways(time, dist)
 }
 
-// Apparently I can use a quadratic equation
+// Apparently I can use a quadratic equation, but the float version was only ever approximate.
+// Part 2 solves the quadratic exactly now, so this is just kept for the name.
 #[aoc(day6, part2, quad)]
-pub fn part2_quad(input: &str) -> usize {
-    let (time, dist) = input
-        .split_once("\n")
-        .map(|(first, second)| (parse_line(first), parse_line(second)))
-        .unwrap();
-    
-    ((time.pow(2) - 4 * dist) as f64).sqrt() as usize
-}
\ No newline at end of file
+pub fn part2_quad(input: &str) -> u128 {
+    part2(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST: &str = "Time:      7  15   30
+                        Distance:  9  40  200";
+
+    // The original brute force, kept as an oracle
+    fn brute(time: u128, dist: u128) -> u128 {
+        (1..time)
+            .filter(|&t| (time - t) * t > dist)
+            .count() as u128
+    }
+
+    #[test]
+    fn part1_test() {
+        assert_eq!(part1(TEST), 288);
+    }
+
+    #[test]
+    fn part2_test() {
+        assert_eq!(part2(TEST), 71503);
+        assert_eq!(part2_quad(TEST), 71503);
+    }
+
+    #[test]
+    fn exact() {
+        assert_eq!(winning_interval(30, 200), Some((11, 19)));
+        assert_eq!(winning_interval(10, 25), None);
+        for time in 0..60 {
+            for dist in 0..time * time / 4 + 2 {
+                assert_eq!(ways(time, dist), brute(time, dist), "time {time}, dist {dist}");
+            }
+        }
+        let time = u64::MAX as u128;
+        let (lo, hi) = winning_interval(time, time * 3).unwrap();
+        assert!((time - lo) * lo > time * 3 && (time - lo + 1) * (lo - 1) <= time * 3);
+        assert_eq!(hi, time - lo);
+        assert_eq!(winning_interval(10, u128::MAX), None);
+        assert_eq!(winning_interval(10, u128::MAX / 4 + 1), None);
+
+        // Far past u64, where T² no longer fits
+        assert_eq!(winning_interval(1 << 70, 0), Some((1, (1 << 70) - 1)));
+        assert_eq!(ways(1 << 70, 0), (1 << 70) - 1);
+        assert_eq!(winning_interval(u128::MAX, u128::MAX), Some((2, u128::MAX - 2)));
+        let travel = |time: u128, t: u128| (time - t).checked_mul(t);
+        for (time, dist) in [(1 << 70, 1 << 100), (u128::MAX, u128::MAX / 3), (1 << 127, u128::MAX)] {
+            let (lo, hi) = winning_interval(time, dist).unwrap();
+            assert!(travel(time, lo).map_or(true, |d| d > dist));
+            assert!(travel(time, lo - 1).is_some_and(|d| d <= dist));
+            assert_eq!(hi, time - lo);
+        }
+    }
+
+    // Checks a model against every hold time
+    fn brute_model(model: &impl RaceModel, time: u128, dist: u128) -> Option<Window> {
+        let wins: Vec<u128> = (0..=time)
+            .filter(|&hold| model.distance(time, hold) > dist as i128)
+            .collect();
+        Some((*wins.first()?, *wins.last()?))
+    }
+
+    #[test]
+    fn models() {
+        for time in 0..40 {
+            for dist in 0..time * time {
+                assert_eq!(cross_check(&Linear(1), time, dist), Ok(winning_interval(time, dist)));
+                assert_eq!(cross_check(&Linear(3), time, dist), Ok(brute_model(&Linear(3), time, dist)));
+                for cap in [0, 1, time / 3, time] {
+                    let model = Capped { rate: 2, cap };
+                    assert_eq!(cross_check(&model, time, dist), Ok(brute_model(&model, time, dist)));
+                }
+                assert_eq!(AccelSquared(1).winning(time, dist), brute_model(&AccelSquared(1), time, dist));
+                for (rate, drag) in [(0, 0), (0, 2), (1, 0), (3, 1), (2, 5)] {
+                    let model = Drag { rate, drag };
+                    assert!(model.analytic(time, dist).is_some());
+                    assert_eq!(cross_check(&model, time, dist), Ok(brute_model(&model, time, dist)));
+                }
+            }
+        }
+    }
+}
@@ -1,41 +1,178 @@
-use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fmt;
 
 type Cards = HashMap<char, usize>;
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
-enum HandType {
-    FiveOfAKind = 7,
-    FourOfAKind = 6,
-    FullHouse = 5,
-    ThreeOfAKind = 4,
-    TwoPair = 3,
-    OnePair = 2,
-    HighCard = 1,
+// A hand category, e.g. a full house, described by the group sizes it needs from largest to
+// smallest. A hand qualifies if each of its groups is at least as big as the one asked for, so
+// [3, 2] is matched by [3, 2] in five cards or [3, 2, 1, 1] in seven.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Category {
+    pub name: String,
+    pub groups: Vec<usize>,
 }
 
-enum Part {
-    One,
-    Two,
+impl Category {
+    pub fn new(name: &str, groups: &[usize]) -> Self {
+        Category { name: name.to_string(), groups: groups.to_vec() }
+    }
+
+    fn matches(&self, same: &[usize]) -> bool {
+        self.groups
+            .iter()
+            .enumerate()
+            .all(|(idx, &need)| same.get(idx).is_some_and(|&have| have >= need))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TieBreak {
+    // Compare cards in the order they were dealt, as in the puzzle
+    Dealt,
+    // Compare the biggest groups first, then the highest ranks, as in poker
+    Grouped,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum HandError {
+    UnknownCard(char),
+    NoCategory(String),
+    TooLarge,
+    NotDealt(String),
 }
 
-fn value(card: char, part: Part) -> usize {
-    match card {
-        card @ '2'..='9' => card.to_digit(10).unwrap()as usize,
-        'T' => 10,
-        'J' => match part {
-            Part::One => 11,
-            Part::Two => 1,
-        },
-        'Q' => 12,
-        'K' => 13,
-        'A' => 14,
-        card => panic!("Not a recognised card: {}", card),
+impl fmt::Display for HandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HandError::UnknownCard(card) => write!(f, "Not a recognised card: {}", card),
+            HandError::NoCategory(hand)  => write!(f, "No category fits the hand {}", hand),
+            HandError::TooLarge          => write!(f, "Hands are too long to pack into a sort key"),
+            HandError::NotDealt(hand)    => write!(f, "The hand {} isn't part of this game", hand),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RuleSet {
+    // Cards from weakest to strongest
+    pub order: Vec<char>,
+    pub wilds: Vec<char>,
+    // Categories from strongest to weakest; the first one a hand matches is the one it gets
+    pub categories: Vec<Category>,
+    pub tie_break: TieBreak,
+}
+
+impl RuleSet {
+    pub fn new(order: &str, wilds: &str, categories: Vec<Category>, tie_break: TieBreak) -> Self {
+        RuleSet {
+            order: order.chars().collect(),
+            wilds: wilds.chars().collect(),
+            categories,
+            tie_break,
+        }
+    }
+
+    pub fn camel_categories() -> Vec<Category> {
+        vec![
+            Category::new("Five of a kind", &[5]),
+            Category::new("Four of a kind", &[4]),
+            Category::new("Full house", &[3, 2]),
+            Category::new("Three of a kind", &[3]),
+            Category::new("Two pair", &[2, 2]),
+            Category::new("One pair", &[2]),
+            Category::new("High card", &[]),
+        ]
+    }
+
+    pub fn standard() -> Self {
+        RuleSet::new("23456789TJQKA", "", RuleSet::camel_categories(), TieBreak::Dealt)
+    }
+
+    pub fn jokers() -> Self {
+        RuleSet::new("J23456789TQKA", "J", RuleSet::camel_categories(), TieBreak::Dealt)
+    }
+
+    pub fn deuces_wild() -> Self {
+        RuleSet::new("23456789TJQKA", "2", RuleSet::camel_categories(), TieBreak::Grouped)
+    }
+
+    pub fn value(&self, card: char) -> Result<usize, HandError> {
+        self.order
+            .iter()
+            .position(|&c| c == card)
+            .ok_or(HandError::UnknownCard(card))
+    }
+
+    // Wilds all join the largest group (the strongest one on a tie), which is always the best
+    // use of them when stronger categories ask for bigger groups. Returns the counts of each
+    // card after the wilds have been assigned, and what the wilds became.
+    fn assign_wilds(&self, hand: &[char]) -> Result<(Cards, Option<char>), HandError> {
+        let mut count = rainman(hand);
+        let wilds: usize = self.wilds.iter().filter_map(|wild| count.remove(wild)).sum();
+        if wilds == 0 {
+            return Ok((count, None));
+        }
+
+        let mut target = *self.order.last().unwrap();
+        let mut best = (0, 0);
+        for (&card, &n) in &count {
+            let key = (n, self.value(card)?);
+            if key > best {
+                best = key;
+                target = card;
+            }
+        }
+        *count.entry(target).or_insert(0) += wilds;
+
+        Ok((count, Some(target)))
+    }
+
+    // Returns the index of the hand's category in the table
+    fn categorise(&self, hand: &[char], count: &Cards) -> Result<usize, HandError> {
+        let mut same: Vec<usize> = count.values().copied().collect();
+        same.sort_unstable_by(|a, b| b.cmp(a));
+
+        self.categories
+            .iter()
+            .position(|cat| cat.matches(&same))
+            .ok_or_else(|| HandError::NoCategory(hand.iter().collect()))
+    }
+
+    // Card values in the order they're compared once the categories are level. Wilds keep their
+    // own value, as with the jokers.
+    fn tie_break_values(&self, hand: &[char]) -> Result<Vec<usize>, HandError> {
+        let mut values = hand
+            .iter()
+            .map(|&card| self.value(card))
+            .collect::<Result<Vec<usize>, _>>()?;
+        if self.tie_break == TieBreak::Grouped {
+            let count = rainman(hand);
+            let mut cards: Vec<(usize, usize)> = count
+                .iter()
+                .map(|(&card, &n)| (n, self.value(card).unwrap()))
+                .collect();
+            cards.sort_unstable_by(|a, b| b.cmp(a));
+            values = cards
+                .into_iter()
+                .flat_map(|(n, value)| std::iter::repeat(value).take(n))
+                .collect();
+        }
+
+        Ok(values)
+    }
+
+    // Sort key for a hand: the category's strength, then the tie-break values
+    pub fn strength(&self, hand: &[char]) -> Result<(usize, Vec<usize>), HandError> {
+        let values = self.tie_break_values(hand)?;
+        let (count, _) = self.assign_wilds(hand)?;
+        let category = self.categorise(hand, &count)?;
+
+        Ok((self.categories.len() - category, values))
     }
 }
 
 // Such a descriptive function name. Well done!
-fn rainman(hand: &Vec<char>) -> Cards {
+fn rainman(hand: &[char]) -> Cards {
     let mut count: Cards = HashMap::new();
 
     hand.iter().for_each(|&card| {
@@ -47,36 +184,142 @@ fn rainman(hand: &Vec<char>) -> Cards {
     count
 }
 
-fn detect_type(hand: Cards, part: Part) -> HandType {
-    let mut same: Vec<usize> = hand.values().copied().collect();
-    same.sort_unstable_by(|a, b| b.cmp(a));
-
-    match part {
-        Part::One => {},
-        Part::Two => {
-            if let Some(jokers) = hand.get(&'J') {
-                if let Some(index) = same.iter().position(|&j| j == *jokers) {
-                    if same.len() > 1 {
-                        same.remove(index);
-                        same[0] += jokers;
-                    }
-                }
-            }
-        }
+// Compiles every hand once into a packed integer key: the category's strength in the top bits,
+// then one field per card holding its tie-break value plus one. Shorter hands are padded with
+// zeros, so comparing keys is the same as comparing (strength, values) lexicographically.
+pub fn compile(input: &[(Vec<char>, usize)], rules: &RuleSet) -> Result<Vec<(u128, usize)>, HandError> {
+    let width = input.iter().map(|(hand, _)| hand.len()).max().unwrap_or(0) as u32;
+    let bits = usize::BITS - rules.order.len().leading_zeros();
+    let cat_bits = usize::BITS - rules.categories.len().leading_zeros();
+    if cat_bits + width * bits > u128::BITS {
+        return Err(HandError::TooLarge);
     }
 
-    match same.as_slice() {
-        [5]       => HandType::FiveOfAKind,
-        [4, 1]    => HandType::FourOfAKind,
-        [3, 2]    => HandType::FullHouse,
-        [3, ..]   => HandType::ThreeOfAKind,
-        [2, 2, 1] => HandType::TwoPair,
-        [2, ..]   => HandType::OnePair,
-        [1, ..]   => HandType::HighCard,
-        _ => panic!("Error in card count"),
+    input
+        .iter()
+        .map(|(hand, bid)| {
+            let (category, values) = rules.strength(hand)?;
+            let key = (0..width as usize).fold(category as u128, |key, idx| {
+                key << bits | values.get(idx).map_or(0, |&value| value as u128 + 1)
+            });
+            Ok((key, *bid))
+        })
+        .collect()
+}
+
+// Ranks every hand from weakest to strongest and totals up the bids
+pub fn winnings(input: &[(Vec<char>, usize)], rules: &RuleSet) -> Result<usize, HandError> {
+    let mut hands = compile(input, rules)?;
+    hands.sort_unstable_by_key(|&(key, _)| key);
+
+    Ok(hands
+        .iter()
+        .enumerate()
+        .map(|(rank, &(_, bid))| (rank + 1) * bid)
+        .sum())
+}
+
+// The original way of ranking, kept as a baseline for `cargo aoc bench`. Each hand's category is
+// worked out once, but the card values are looked up again every time two hands of the same
+// category get compared.
+pub fn winnings_by_comparison(input: &[(Vec<char>, usize)], rules: &RuleSet) -> Result<usize, HandError> {
+    let mut hands = input
+        .iter()
+        .map(|(hand, bid)| {
+            rules.tie_break_values(hand)?;
+            let (count, _) = rules.assign_wilds(hand)?;
+            Ok((rules.categories.len() - rules.categorise(hand, &count)?, hand, bid))
+        })
+        .collect::<Result<Vec<_>, HandError>>()?;
+    hands.sort_by(|(cat1, hand1, _), (cat2, hand2, _)| {
+        cat1.cmp(cat2).then_with(|| {
+            let values = |hand| rules.tie_break_values(hand).unwrap();
+            values(hand1).cmp(&values(hand2))
+        })
+    });
+
+    Ok(hands
+        .iter()
+        .enumerate()
+        .map(|(rank, (_, _, &bid))| (rank + 1) * bid)
+        .sum())
+}
+
+// Everything that went into ranking a hand
+#[derive(Debug, PartialEq)]
+pub struct Explanation {
+    pub hand: String,
+    pub category: String,
+    // Position of each wild card and the card it stood in for
+    pub wilds: Vec<(usize, char)>,
+    pub tie_break: Vec<usize>,
+    pub rank: usize,
+    pub bid: usize,
+    pub winnings: usize,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>5}  {}  {:<15}", self.rank, self.hand, self.category)?;
+        let wilds: Vec<String> = self.wilds
+            .iter()
+            .map(|(pos, card)| format!("{}@{}->{}", self.hand.chars().nth(*pos).unwrap(), pos, card))
+            .collect();
+        write!(f, "  wilds [{}]  tie-break {:?}", wilds.join(" "), self.tie_break)?;
+        write!(f, "  {} x {} = {}", self.rank, self.bid, self.winnings)
     }
 }
 
+// Explains every hand in the game, from weakest to strongest
+pub fn explain_all(input: &[(Vec<char>, usize)], rules: &RuleSet) -> Result<Vec<Explanation>, HandError> {
+    let keys = compile(input, rules)?;
+    let mut order: Vec<usize> = (0..input.len()).collect();
+    order.sort_unstable_by_key(|&idx| keys[idx].0);
+
+    order
+        .into_iter()
+        .enumerate()
+        .map(|(rank, idx)| {
+            let (hand, bid) = &input[idx];
+            let (count, target) = rules.assign_wilds(hand)?;
+            let category = rules.categorise(hand, &count)?;
+            let wilds = hand
+                .iter()
+                .enumerate()
+                .filter(|(_, card)| rules.wilds.contains(card))
+                .map(|(pos, _)| (pos, target.unwrap()))
+                .collect();
+
+            Ok(Explanation {
+                hand: hand.iter().collect(),
+                category: rules.categories[category].name.clone(),
+                wilds,
+                tie_break: rules.tie_break_values(hand)?,
+                rank: rank + 1,
+                bid: *bid,
+                winnings: (rank + 1) * bid,
+            })
+        })
+        .collect()
+}
+
+pub fn explain(hand: &str, input: &[(Vec<char>, usize)], rules: &RuleSet) -> Result<Explanation, HandError> {
+    explain_all(input, rules)?
+        .into_iter()
+        .find(|explanation| explanation.hand == hand)
+        .ok_or_else(|| HandError::NotDealt(hand.to_string()))
+}
+
+// One line per hand, weakest first, for printing wherever the listing is wanted
+pub fn listing(input: &[(Vec<char>, usize)], rules: &RuleSet) -> Result<String, HandError> {
+    let lines: Vec<String> = explain_all(input, rules)?
+        .iter()
+        .map(|explanation| explanation.to_string())
+        .collect();
+
+    Ok(lines.join("\n"))
+}
+
 #[aoc_generator(day7)]
 pub fn input_generator(input: &str) -> Vec<(Vec<char>, usize)> {
     input
@@ -95,64 +338,31 @@ pub fn input_generator(input: &str) -> Vec<(Vec<char>, usize)> {
 }
 
 #[aoc(day7, part1)]
-pub fn solve_part1(input: &Vec<(Vec<char>, usize)>) -> usize {
-    let mut hands: Vec<(&Vec<char>, &usize, HandType)> = Vec::new();
-    
-    for (hand, bid) in input {
-        let counted = rainman(hand);
-        let handtype = detect_type(counted, Part::One);
-        hands.push((hand, bid, handtype));
-    }
-
-    hands.sort_by(|(vec1, _, handtype1), (vec2, _, handtype2)| {
-        match handtype1.cmp(handtype2) {
-            Ordering::Equal => {
-                vec1.iter().map(|&card| value(card, Part::One)).cmp(vec2.iter().map(|&card| value(card, Part::One)))
-            }
-            other => other,
-        }
-    });
-
-    let score: usize = hands
-        .iter()
-        .enumerate()
-        .map(|(rank, hand)| (rank + 1) * hand.1)
-        .sum();
-
-    score
+pub fn solve_part1(input: &[(Vec<char>, usize)]) -> usize {
+    winnings(input, &RuleSet::standard()).unwrap_or_else(|err| panic!("{err}"))
 }
 
 #[aoc(day7, part2)]
-pub fn solve_part2(input: &Vec<(Vec<char>, usize)>) -> usize {
-    let mut hands: Vec<(&Vec<char>, &usize, HandType)> = Vec::new();
-
-    for (hand, bid) in input {
-        let counted = rainman(hand);
-        let handtype = detect_type(counted, Part::Two);
-        hands.push((hand, bid, handtype));
-    }
-
-    hands.sort_by(|(vec1, _, handtype1), (vec2, _, handtype2)| {
-        match handtype1.cmp(handtype2) {
-            Ordering::Equal => {
-                vec1.iter().map(|&card| value(card, Part::Two)).cmp(vec2.iter().map(|&card| value(card, Part::Two)))
-            }
-            other => other,
-        }
-    });
+pub fn solve_part2(input: &[(Vec<char>, usize)]) -> usize {
+    winnings(input, &RuleSet::jokers()).unwrap_or_else(|err| panic!("{err}"))
+}
 
-    let score: usize = hands
-        .iter()
-        .enumerate()
-        .map(|(rank, hand)| (rank + 1) * hand.1)
-        .sum();
+#[aoc(day7, part1, comparison)]
+pub fn solve_part1_comparison(input: &[(Vec<char>, usize)]) -> usize {
+    winnings_by_comparison(input, &RuleSet::standard()).unwrap_or_else(|err| panic!("{err}"))
+}
 
-    score
+#[aoc(day7, part2, comparison)]
+pub fn solve_part2_comparison(input: &[(Vec<char>, usize)]) -> usize {
+    winnings_by_comparison(input, &RuleSet::jokers()).unwrap_or_else(|err| panic!("{err}"))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::HashSet;
+    use std::time::Instant;
 
     const TEST: &str = "32T3K 765
     T55J5 684
@@ -169,4 +379,95 @@ mod tests {
     fn part2() {
         assert_eq!(solve_part2(&input_generator(TEST)), 5905);
     }
-}
\ No newline at end of file
+
+    // Hands are kept unique, as tied hands could be ranked either way round
+    fn random_hands(rng: &mut StdRng, count: usize, len: usize) -> Vec<(Vec<char>, usize)> {
+        let cards: Vec<char> = "23456789TJQKA".chars().collect();
+        let mut seen = HashSet::new();
+        (0..count)
+            .map(|_| {
+                let hand: Vec<char> = (0..len).map(|_| cards[rng.gen_range(0..cards.len())]).collect();
+                (hand, rng.gen_range(0..1000))
+            })
+            .filter(|(hand, _)| seen.insert(hand.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn packed_keys() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for rules in [RuleSet::standard(), RuleSet::jokers(), RuleSet::deuces_wild()] {
+            for len in [3, 5, 7] {
+                let input = random_hands(&mut rng, 500, len);
+                let keys = compile(&input, &rules).unwrap();
+                for idx in 1..input.len() {
+                    let (prev, next) = (rules.strength(&input[idx - 1].0), rules.strength(&input[idx].0));
+                    assert_eq!(keys[idx - 1].0.cmp(&keys[idx].0), prev.unwrap().cmp(&next.unwrap()));
+                }
+                // Only dealt order guarantees distinct hands never tie
+                if rules.tie_break == TieBreak::Dealt {
+                    assert_eq!(winnings(&input, &rules), winnings_by_comparison(&input, &rules));
+                }
+            }
+        }
+        let mixed: Vec<(Vec<char>, usize)> = ["AA", "AAK", "AK", "A"]
+            .iter()
+            .map(|hand| (hand.chars().collect(), 1))
+            .collect();
+        let keys = compile(&mixed, &RuleSet::standard()).unwrap();
+        assert!(keys[3].0 < keys[2].0 && keys[2].0 < keys[0].0 && keys[0].0 < keys[1].0);
+        assert_eq!(compile(&random_hands(&mut rng, 1, 40), &RuleSet::standard()), Err(HandError::TooLarge));
+    }
+
+    // Run with `cargo test --release -- --ignored`
+    #[test]
+    #[ignore]
+    fn packed_keys_bench() {
+        let input = random_hands(&mut StdRng::seed_from_u64(7), 100_000, 5);
+        let rules = RuleSet::jokers();
+        let timer = Instant::now();
+        let packed = winnings(&input, &rules);
+        let packed_time = timer.elapsed();
+        let timer = Instant::now();
+        let compared = winnings_by_comparison(&input, &rules);
+        let compared_time = timer.elapsed();
+        assert_eq!(packed, compared);
+        assert!(packed_time < compared_time, "{packed_time:?} vs {compared_time:?}");
+    }
+
+    #[test]
+    fn rule_sets() {
+        let hand = |cards: &str| cards.chars().collect::<Vec<char>>();
+        let deuces = RuleSet::deuces_wild();
+        assert_eq!(deuces.strength(&hand("2KK2K")).unwrap().0, 7);
+        assert_eq!(deuces.strength(&hand("22222")).unwrap().0, 7);
+        assert_eq!(deuces.strength(&hand("A2345")).unwrap().0, 2);
+
+        let multi = RuleSet::new("23456789TJQKA", "2J", RuleSet::camel_categories(), TieBreak::Dealt);
+        assert_eq!(multi.strength(&hand("2J9KA")).unwrap().0, 4);
+
+        let seven = RuleSet::standard();
+        assert_eq!(seven.strength(&hand("AAAKK23")).unwrap().0, 5);
+        assert_eq!(seven.strength(&hand("AAKK223")).unwrap().0, 3);
+
+        assert_eq!(seven.strength(&hand("AAX")), Err(HandError::UnknownCard('X')));
+    }
+
+    #[test]
+    fn explanations() {
+        let input = input_generator(TEST);
+        let explanation = explain("KTJJT", &input, &RuleSet::jokers()).unwrap();
+        assert_eq!(explanation.category, "Four of a kind");
+        assert_eq!(explanation.wilds, vec![(2, 'T'), (3, 'T')]);
+        assert_eq!(explanation.tie_break, vec![11, 9, 0, 0, 9]);
+        assert_eq!((explanation.rank, explanation.winnings), (5, 1100));
+        assert_eq!(explain("KTJJT", &input, &RuleSet::standard()).unwrap().rank, 2);
+        assert_eq!(explain("AAAAA", &input, &RuleSet::standard()), Err(HandError::NotDealt("AAAAA".to_string())));
+
+        let total: usize = explain_all(&input, &RuleSet::jokers()).unwrap().iter().map(|e| e.winnings).sum();
+        assert_eq!(total, 5905);
+        let listing = listing(&input, &RuleSet::jokers()).unwrap();
+        assert_eq!(listing.lines().count(), 5);
+        assert!(listing.lines().nth(4).unwrap().contains("KTJJT"));
+    }
+}
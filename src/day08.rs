@@ -1,99 +1,271 @@
-use num::integer::lcm;
+use num::integer::{gcd, lcm};
 use std::collections::HashMap;
+use std::fmt;
 
-type Network = HashMap<String, (String, String)>;
-
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Direction {
     Left,
     Right,
 }
 
+// Node labels are interned into dense indices, so walking the network is just indexing into
+// `adjacency`, which holds each node's [left, right] neighbours.
+#[derive(Debug)]
+pub struct Network {
+    pub directions: Vec<Direction>,
+    pub labels: Vec<String>,
+    pub adjacency: Vec<[u32; 2]>,
+    // Where each node ends up after one full pass of the directions
+    pub pass: Vec<u32>,
+    index: HashMap<String, u32>,
+}
+
+impl Network {
+    fn intern(&mut self, label: &str) -> u32 {
+        if let Some(&idx) = self.index.get(label) {
+            return idx;
+        }
+        let idx = self.labels.len() as u32;
+        self.labels.push(label.to_string());
+        self.adjacency.push([idx, idx]);
+        self.index.insert(label.to_string(), idx);
+
+        idx
+    }
+
+    pub fn node(&self, label: &str) -> Option<u32> {
+        self.index.get(label).copied()
+    }
+
+    pub fn step(&self, node: u32, direction: Direction) -> u32 {
+        self.adjacency[node as usize][direction as usize]
+    }
+
+    // Every node visited over one pass of the directions, starting with `node` itself
+    fn walk_pass(&self, node: u32) -> impl Iterator<Item = u32> + '_ {
+        self.directions.iter().scan(node, move |cnode, &direction| {
+            let current = *cnode;
+            *cnode = self.step(current, direction);
+            Some(current)
+        })
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph network {\n");
+        for (idx, &[left, right]) in self.adjacency.iter().enumerate() {
+            let label = &self.labels[idx];
+            dot += &format!("    \"{}\" -> \"{}\" [label=\"L\"];\n", label, self.labels[left as usize]);
+            dot += &format!("    \"{}\" -> \"{}\" [label=\"R\"];\n", label, self.labels[right as usize]);
+        }
+        dot + "}\n"
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum GhostError {
+    NoGhosts,
+    // No step lands every ghost on an end node at once
+    Unsatisfiable,
+}
+
+impl fmt::Display for GhostError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GhostError::NoGhosts      => write!(f, "There are no start nodes"),
+            GhostError::Unsatisfiable => write!(f, "The ghosts never all reach an end node together"),
+        }
+    }
+}
+
+// The walk from one start node, which has to loop eventually because there are only so many
+// (node, direction index) states. It runs for `pre_period` steps before entering a cycle of
+// `cycle` steps. End nodes are hit at each step in `pre_hits`, and at each step in `cycle_hits`
+// plus any multiple of the cycle length.
+#[derive(Debug, PartialEq)]
+pub struct Trajectory {
+    pub pre_period: usize,
+    pub cycle: usize,
+    pub pre_hits: Vec<usize>,
+    pub cycle_hits: Vec<usize>,
+}
+
+impl Trajectory {
+    // States are only compared at the start of each pass of the directions, where the pass table
+    // can jump straight to the next one. The pre-period and cycle found are whole passes, which
+    // may be a multiple of the shortest cycle, but that's no problem for the CRT.
+    pub fn trace(network: &Network, start: u32, is_end: impl Fn(&str) -> bool) -> Self {
+        let len = network.directions.len();
+        let ends: Vec<bool> = network.labels.iter().map(|label| is_end(label)).collect();
+        let mut seen = vec![usize::MAX; network.labels.len()];
+        let mut hits = Vec::new();
+        let mut cnode = start;
+        let mut passes = 0;
+
+        while seen[cnode as usize] == usize::MAX {
+            seen[cnode as usize] = passes;
+            hits.extend(network
+                .walk_pass(cnode)
+                .enumerate()
+                .filter(|&(_, node)| ends[node as usize])
+                .map(|(offset, _)| passes * len + offset));
+            cnode = network.pass[cnode as usize];
+            passes += 1;
+        }
+        let pre_period = seen[cnode as usize] * len;
+        let cycle_hits = hits.split_off(hits.partition_point(|&hit| hit < pre_period));
+
+        Trajectory { pre_period, cycle: passes * len - pre_period, pre_hits: hits, cycle_hits }
+    }
+
+    pub fn hits(&self, step: usize) -> bool {
+        if step < self.pre_period {
+            self.pre_hits.contains(&step)
+        } else {
+            let offset = self.pre_period + (step - self.pre_period) % self.cycle;
+            self.cycle_hits.contains(&offset)
+        }
+    }
+}
+
+// Generalised Chinese Remainder Theorem: merges x ≡ a (mod m) and x ≡ b (mod n), where m and n
+// needn't be coprime. Returns the combined residue and modulus, or None if they conflict.
+fn crt((a, m): (i128, i128), (b, n): (i128, i128)) -> Option<(i128, i128)> {
+    let g = gcd(m, n);
+    if (b - a) % g != 0 {
+        return None;
+    }
+    let (_, inv, _) = extended_gcd(m / g, n / g);
+    let modulus = lcm(m, n);
+    let k = ((b - a) / g % (n / g) * inv).rem_euclid(n / g);
+
+    Some(((a + m * k).rem_euclid(modulus), modulus))
+}
+
+// Returns (g, x, y) such that ax + by = g
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - a / b * y)
+    }
+}
+
+// Finds the first step on which every ghost is on an end node. Before the last ghost settles into
+// its cycle the steps are simply checked one by one. After that each ghost pins the step to one of
+// its cycle hits modulo its cycle length, and every combination of those is merged with the CRT.
+// Each residue kept is distinct modulo the LCM of the cycles merged so far, so the list never
+// grows past that LCM, but it can be as long as the product of every ghost's hit count. That's
+// one residue per ghost on the puzzle input, and a handful of ghosts with a few hits each is
+// still fine, but dozens of hits across six ghosts would need something smarter.
+pub fn ghost_walk(trajectories: &[Trajectory]) -> Result<usize, GhostError> {
+    if trajectories.is_empty() {
+        return Err(GhostError::NoGhosts);
+    }
+    let settled = trajectories.iter().map(|traj| traj.pre_period).max().unwrap();
+    if let Some(step) = (0..settled).find(|&step| trajectories.iter().all(|traj| traj.hits(step))) {
+        return Ok(step);
+    }
+
+    let mut residues = vec![(0_i128, 1_i128)];
+    for traj in trajectories {
+        let mut merged: Vec<(i128, i128)> = residues
+            .iter()
+            .flat_map(|&residue| {
+                traj.cycle_hits
+                    .iter()
+                    .filter_map(move |&hit| crt(residue, (hit as i128, traj.cycle as i128)))
+            })
+            .collect();
+        merged.sort_unstable();
+        merged.dedup();
+        residues = merged;
+    }
+
+    residues
+        .iter()
+        .map(|&(residue, modulus)| {
+            let behind = (settled as i128 - residue).max(0);
+            residue + (behind + modulus - 1) / modulus * modulus
+        })
+        .min()
+        .map(|step| step as usize)
+        .ok_or(GhostError::Unsatisfiable)
+}
+
 #[aoc_generator(day8)]
-pub fn input_generator(input: &str) -> (Vec<Direction>, Network) {
-    input
-        .split_once("\n\n")
-        .map(|(dirs, network)| (
-            dirs
-                .chars()
-                .map(|c| {
-                    match c {
-                        'L' => Direction::Left,
-                        'R' => Direction::Right,
-                        c   => panic!("Unknown direction {}.", c),
-                    }
-                })
-                .collect(),
-            network
-                .lines()
-                .map(|line| {
-                    line.split_once(" = ")
-                        .map(|(key, nodes)| (
-                            key.to_string(),
-                            nodes
-                                .trim_matches(|c| c == '(' || c == ')')
-                                .split_once(", ")
-                                .map(|(left, right)| (
-                                    left.to_string(),
-                                    right.to_string()
-                                ))
-                                .unwrap()
-                        ))
-                        .unwrap()
-                })
-                .collect()
-        ))
-        .unwrap()
+pub fn input_generator(input: &str) -> Network {
+    let (dirs, nodes) = input.split_once("\n\n").unwrap();
+    let mut network = Network {
+        directions: dirs
+            .trim()
+            .chars()
+            .map(|c| {
+                match c {
+                    'L' => Direction::Left,
+                    'R' => Direction::Right,
+                    c   => panic!("Unknown direction {}.", c),
+                }
+            })
+            .collect(),
+        labels: Vec::new(),
+        adjacency: Vec::new(),
+        pass: Vec::new(),
+        index: HashMap::new(),
+    };
+
+    // Anything only ever named as a neighbour would be stuck looping on itself
+    let mut keys = Vec::new();
+    for line in nodes.lines() {
+        let (key, (left, right)) = line
+            .trim()
+            .split_once(" = ")
+            .map(|(key, nodes)| (
+                key,
+                nodes
+                    .trim_matches(|c| c == '(' || c == ')')
+                    .split_once(", ")
+                    .unwrap()
+            ))
+            .unwrap();
+        let key = network.intern(key);
+        let left = network.intern(left);
+        let right = network.intern(right);
+        network.adjacency[key as usize] = [left, right];
+        keys.push(key);
+    }
+    let mut defined = vec![false; network.labels.len()];
+    keys.into_iter().for_each(|key| defined[key as usize] = true);
+    let undefined: Vec<&str> = network.labels
+        .iter()
+        .zip(&defined)
+        .filter(|(_, &defined)| !defined)
+        .map(|(label, _)| label.as_str())
+        .collect();
+    if !undefined.is_empty() {
+        panic!("Undefined nodes {}.", undefined.join(", "));
+    }
+
+    network.pass = (0..network.labels.len() as u32)
+        .map(|node| {
+            network.directions
+                .iter()
+                .fold(node, |cnode, &direction| network.step(cnode, direction))
+        })
+        .collect();
+
+    network
 }
 
 #[aoc(day8, part1)]
-pub fn solve_part1(input: &(Vec<Direction>, Network)) -> usize {
-    let mut cnode = String::from("AAA");
-    let mut direction = input.0.iter().cycle();
-    let mut steps = 0;
-
-    while cnode != "ZZZ" {
-        let (left, right) = input.1[&cnode].clone();
-        cnode = match direction.next().unwrap() {
-            Direction::Left  => left,
-            Direction::Right => right,
-        };
-
-        steps += 1;
-    };
-    
-    steps
+pub fn solve_part1(input: &Network) -> usize {
+    let trajectory = Trajectory::trace(input, input.node("AAA").unwrap(), |node| node == "ZZZ");
+
+    ghost_walk(&[trajectory]).unwrap_or_else(|err| panic!("{err}"))
 }
 
-// This isn't going to work. I can't wait until the heat death of the Universe.
+// Walking the ghosts in step isn't going to work. I can't wait until the heat death of the Universe.
 // The final answer is in the order of 18.6 x 10^12
-// #[aoc(day8, part2)]
-// pub fn solve_part2(input: &(Vec<Direction>, Network)) -> usize {
-//     let mut steps = 0;
-//     let mut cnodes: Vec<String> = input.1
-//         .clone()
-//         .into_keys()
-//         .filter(|k| k.ends_with('A'))
-//         .collect();
-//     let mut direction = input.0.iter().cycle();
-    
-//     while !cnodes.iter().all(|node| node.ends_with('Z')) {
-//         let mut new_cnodes = Vec::new();
-//         let turn = direction.next().unwrap();
-
-//         for cnode in cnodes.iter_mut() {
-//             let (left, right) = input.1.get(cnode).unwrap().clone();
-//             let new_cnode = match turn {
-//                 Direction::Left  => left,
-//                 Direction::Right => right,
-//             };
-//             new_cnodes.push(new_cnode);
-//         }
-//         cnodes = new_cnodes;
-//         steps += 1;
-//     }
-    
-//     steps
-// }
 
 // Apparently the best course of action is to use LCM. This was kind of subtly hinted at in the puzzle.
 // Six nodes end in 'A'. These are listed below with their cycle lengths:
@@ -105,31 +277,122 @@ pub fn solve_part1(input: &(Vec<Direction>, Network)) -> usize {
 // VSA 17873
 // Fortunately mine syncs at step 0, whereas it seems others are not so lucky.
 // Another thing of note is that the number of directions given is a prime number: 293.
+
+// The LCM only works because of that sync, so this solves the general case instead. Each ghost's
+// walk is traced until it repeats, giving the steps where it's on an end node before the cycle
+// and the offsets where it is within the cycle. The cycles are then lined up with the CRT, which
+// copes with offsets that aren't zero and with cycle lengths that share factors.
 #[aoc(day8, part2)]
-pub fn solve_part2(input: &(Vec<Direction>, Network)) -> usize {
-    let cnodes: Vec<String> = input.1
-        .clone()
-        .into_keys()
+pub fn solve_part2(input: &Network) -> usize {
+    let trajectories: Vec<Trajectory> = input.labels
+        .iter()
         .filter(|k| k.ends_with('A'))
+        .map(|start| Trajectory::trace(input, input.node(start).unwrap(), |node| node.ends_with('Z')))
         .collect();
 
-    // LCM magic
-    cnodes.iter()
-        .map(|cnode| {
-            let mut cnode = cnode.to_owned();
-            let mut direction = input.0.iter().cycle();
-            let mut steps = 0;
-
-            while !cnode.ends_with('Z') {
-                let (left, right) = input.1[&cnode].clone();
-                cnode = match direction.next().unwrap() {
-                    Direction::Left  => left,
-                    Direction::Right => right,
-                };
-                steps += 1;
-            }
-
-            steps
-        })
-        .fold(1, lcm)
-}
\ No newline at end of file
+    ghost_walk(&trajectories).unwrap_or_else(|err| panic!("{err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST1: &str = "LLR
+
+                         AAA = (BBB, BBB)
+                         BBB = (AAA, ZZZ)
+                         ZZZ = (ZZZ, ZZZ)";
+
+    const TEST2: &str = "LR
+
+                         11A = (11B, XXX)
+                         11B = (XXX, 11Z)
+                         11Z = (11B, XXX)
+                         22A = (22B, XXX)
+                         22B = (22C, 22C)
+                         22C = (22Z, 22Z)
+                         22Z = (22B, 22B)
+                         XXX = (XXX, XXX)";
+
+    // Ghosts that first reach an end node at steps 2 and 1 and then every 3 and 4 steps, so
+    // taking the LCM of first arrivals would give 2
+    const OFFSET: &str = "L
+
+                          1XA = (1XB, 1XB)
+                          1XB = (1XZ, 1XZ)
+                          1XZ = (1XC, 1XC)
+                          1XC = (1XB, 1XB)
+                          2XA = (2XZ, 2XZ)
+                          2XZ = (2XB, 2XB)
+                          2XB = (2XC, 2XC)
+                          2XC = (2XD, 2XD)
+                          2XD = (2XZ, 2XZ)";
+
+    // One ghost is only ever on an end node at odd steps and the other at even steps
+    const NEVER: &str = "L
+
+                         1XA = (1XZ, 1XZ)
+                         1XZ = (1XB, 1XB)
+                         1XB = (1XZ, 1XZ)
+                         2XA = (2XB, 2XB)
+                         2XB = (2XZ, 2XZ)
+                         2XZ = (2XB, 2XB)";
+
+    #[test]
+    fn part1() {
+        assert_eq!(solve_part1(&input_generator(TEST1)), 6);
+    }
+
+    #[test]
+    fn part2() {
+        assert_eq!(solve_part2(&input_generator(TEST2)), 6);
+    }
+
+    #[test]
+    fn offsets() {
+        assert_eq!(solve_part2(&input_generator(OFFSET)), 5);
+
+        let input = input_generator(NEVER);
+        let trajectories: Vec<Trajectory> = ["1XA", "2XA"]
+            .iter()
+            .map(|start| Trajectory::trace(&input, input.node(start).unwrap(), |node| node.ends_with('Z')))
+            .collect();
+        assert_eq!(trajectories[0].cycle, 2);
+        assert_eq!(trajectories[0].cycle_hits, vec![1]);
+        assert_eq!(ghost_walk(&trajectories), Err(GhostError::Unsatisfiable));
+    }
+
+    #[test]
+    fn network() {
+        let input = input_generator(TEST1);
+        let (aaa, bbb, zzz) = (input.node("AAA").unwrap(), input.node("BBB").unwrap(), input.node("ZZZ").unwrap());
+        assert_eq!(input.adjacency[bbb as usize], [aaa, zzz]);
+        assert_eq!(input.pass[aaa as usize], bbb);
+        assert_eq!(input.pass[bbb as usize], zzz);
+        assert!(input.to_dot().contains("\"BBB\" -> \"ZZZ\" [label=\"R\"];"));
+    }
+
+    #[test]
+    fn several_hits() {
+        let ghosts = [
+            Trajectory { pre_period: 0, cycle: 6, pre_hits: vec![], cycle_hits: vec![1, 4, 5] },
+            Trajectory { pre_period: 3, cycle: 10, pre_hits: vec![2], cycle_hits: vec![3, 7, 8, 12] },
+            Trajectory { pre_period: 0, cycle: 15, pre_hits: vec![], cycle_hits: vec![2, 11, 13] },
+            Trajectory { pre_period: 1, cycle: 4, pre_hits: vec![0], cycle_hits: vec![1, 3, 4] },
+        ];
+        for count in 1..=ghosts.len() {
+            let ghosts = &ghosts[..count];
+            let first = (0..10_000).find(|&step| ghosts.iter().all(|ghost| ghost.hits(step)));
+            assert_eq!(ghost_walk(ghosts).ok(), first);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Undefined nodes ZZY, CCC.")]
+    fn undefined_nodes() {
+        input_generator("LLR
+
+                         AAA = (BBB, ZZY)
+                         BBB = (CCC, AAA)");
+    }
+}
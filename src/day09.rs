@@ -1,57 +1,179 @@
+use num::rational::Ratio;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum SeriesError {
+    // An empty series, so there's no polynomial to extend
+    NotPolynomial,
+    Overflow,
+}
+
+impl fmt::Display for SeriesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SeriesError::NotPolynomial => write!(f, "There are no values to extend"),
+            SeriesError::Overflow      => write!(f, "The extrapolated value doesn't fit in an i128"),
+        }
+    }
+}
+
+// A sequence sampled at indices 0, 1, 2, ... and described by its finite differences
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub values: Vec<i128>,
+    // The first entry of each row of the difference table: Δ⁰y₀, Δ¹y₀, Δ²y₀, ...
+    newton: Vec<i128>,
+    degree: Option<usize>,
+}
+
+impl Series {
+    // Hmm... I still don't like recursive calls, so the difference table is built row by row.
+    // If it runs down to a single difference before hitting all zeroes, that difference is the
+    // top-degree term, just as the original recursion treated it.
+    pub fn new(values: Vec<i128>) -> Self {
+        let mut newton = Vec::new();
+        let mut degree = None;
+        let mut row = values.clone();
+
+        while let Some(&first) = row.first() {
+            if row.iter().all(|&val| val == 0) {
+                degree = Some(newton.len().saturating_sub(1));
+                break;
+            }
+            newton.push(first);
+            if row.len() == 1 {
+                degree = Some(newton.len() - 1);
+                break;
+            }
+            row = row.windows(2).map(|window| window[1] - window[0]).collect();
+        }
+
+        Series { values, newton, degree }
+    }
+
+    // Degree of the lowest polynomial through the values, which is at most one less than the
+    // number of values. A constant series (zero included) has degree 0, and an empty one has none.
+    pub fn degree(&self) -> Option<usize> {
+        self.degree
+    }
+
+    // Coefficients of the Newton forward-difference form, p(x) = Σ Δʲy₀ · C(x, j)
+    pub fn newton(&self) -> Result<&[i128], SeriesError> {
+        self.degree.ok_or(SeriesError::NotPolynomial)?;
+        Ok(&self.newton)
+    }
+
+    // Coefficients of p(x) = a₀ + a₁x + a₂x² + ..., found by expanding each binomial in the Newton form
+    pub fn coefficients(&self) -> Result<Vec<Ratio<i128>>, SeriesError> {
+        let newton = self.newton()?;
+        let mut coefs = vec![Ratio::from_integer(0); newton.len().max(1)];
+        // x(x - 1)...(x - j + 1) / j!
+        let mut binomial = vec![Ratio::from_integer(1)];
+
+        for (j, &delta) in newton.iter().enumerate() {
+            for (coef, term) in coefs.iter_mut().zip(&binomial) {
+                *coef += term * delta;
+            }
+            let mut next = vec![Ratio::from_integer(0); binomial.len() + 1];
+            for (power, &term) in binomial.iter().enumerate() {
+                next[power + 1] += term / (j as i128 + 1);
+                next[power] -= term * j as i128 / (j as i128 + 1);
+            }
+            binomial = next;
+        }
+
+        Ok(coefs)
+    }
+
+    // Value at any index, forwards or backwards, in O(degree). C(x, j) is built up one term at a
+    // time, and C(x, j - 1)·(x - j + 1) is always divisible by j, even for negative x.
+    pub fn extrapolate(&self, index: i128) -> Result<i128, SeriesError> {
+        let overflow = || SeriesError::Overflow;
+        let mut binomial: i128 = 1;
+        let mut total: i128 = 0;
+
+        for (j, &delta) in self.newton()?.iter().enumerate() {
+            if j > 0 {
+                let j = j as i128;
+                binomial = binomial.checked_mul(index - j + 1).ok_or_else(overflow)? / j;
+            }
+            total = total
+                .checked_add(binomial.checked_mul(delta).ok_or_else(overflow)?)
+                .ok_or_else(overflow)?;
+        }
+
+        Ok(total)
+    }
+
+    pub fn next(&self) -> Result<i128, SeriesError> {
+        self.extrapolate(self.values.len() as i128)
+    }
+
+    pub fn previous(&self) -> Result<i128, SeriesError> {
+        self.extrapolate(-1)
+    }
+}
+
 #[aoc_generator(day9)]
-pub fn input_generator(input: &str) -> Vec<Vec<i32>> {
+pub fn input_generator(input: &str) -> Vec<Series> {
     input
         .lines()
         .map(|line| {
-            line
-                .trim()
+            Series::new(line
                 .split_whitespace()
-                .map(|num| num.parse::<i32>().unwrap())
-                .collect()
+                .map(|num| num.parse::<i128>().unwrap())
+                .collect())
         })
         .collect()
 }
 
-// Hmm... I don't like this redundancy, but I also don't like recursive calls...
-fn extrapolate(series: &Vec<i32>) -> i32 {
-    let mut all_zeroes = true;
-    let mut deltas: Vec<i32> = series.windows(2)
-        .map(|window| {
-            let delta = window[1] - window[0];
-            all_zeroes = all_zeroes && delta == 0;
-            delta
-        })
-        .collect();
+#[aoc(day9, part1)]
+pub fn solve_part1(input: &[Series]) -> i128 {
+    input.iter().map(|series| series.next().unwrap_or_else(|err| panic!("{err}"))).sum()
+}
 
-    let mut end_num = *series.last().unwrap();
+#[aoc(day9, part2)]
+pub fn solve_part2(input: &[Series]) -> i128 {
+    input.iter().map(|series| series.previous().unwrap_or_else(|err| panic!("{err}"))).sum()
+}
 
-    while !all_zeroes {
-        let current_deltas = deltas.windows(2)
-            .map(|window| {
-                let delta = window[1] - window[0];
-                all_zeroes = all_zeroes && delta == 0;
-                delta
-            })
-            .collect();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        if deltas.is_empty() {
-            break;
-        }
+    const TEST: &str = "0 3 6 9 12 15
+                        1 3 6 10 15 21
+                        10 13 16 21 30 45";
 
-        end_num += deltas.last().unwrap_or(&0);
+    #[test]
+    fn part1() {
+        assert_eq!(solve_part1(&input_generator(TEST)), 114);
+    }
 
-        deltas = current_deltas;
+    #[test]
+    fn part2() {
+        assert_eq!(solve_part2(&input_generator(TEST)), 2);
     }
 
-    end_num
-}
+    #[test]
+    fn series() {
+        let input = input_generator(TEST);
+        assert_eq!(input.iter().map(|series| series.degree()).collect::<Vec<_>>(), vec![Some(1), Some(2), Some(3)]);
+        let half = |n| Ratio::new(n, 2);
+        assert_eq!(input[1].coefficients().unwrap(), vec![half(2), half(3), half(1)]);
+        assert_eq!(input[2].extrapolate(-3), Ok(-19));
+        assert_eq!(input[0].extrapolate(1_000_000), Ok(3_000_000));
+        assert_eq!(Series::new(vec![7, 7, 7]).coefficients(), Ok(vec![Ratio::from_integer(7)]));
 
-#[aoc(day9, part1)]
-pub fn solve_part1(input: &Vec<Vec<i32>>) -> i32 {
-    input.iter().map(|series| extrapolate(&series)).sum()
+        // Too short to reach all zeroes, so the last difference is taken as the top-degree term
+        let doubling = Series::new(vec![1, 2, 4, 8]);
+        assert_eq!(doubling.degree(), Some(3));
+        assert_eq!(doubling.next(), Ok(15));
+        assert_eq!(Series::new(vec![1, 2]).next(), Ok(3));
+        assert_eq!(Series::new(vec![5]).previous(), Ok(5));
+        assert_eq!(solve_part1(&input_generator("1 2\n3 1 4")), 3 + 12);
+        assert_eq!(Series::new(Vec::new()).next(), Err(SeriesError::NotPolynomial));
+        let steep = Series::new(vec![0, 1, 16, 81, 256, 625]);
+        assert_eq!(steep.extrapolate(i128::MAX / 2), Err(SeriesError::Overflow));
+    }
 }
-
-#[aoc(day9, part2)]
-pub fn solve_part2(input: &Vec<Vec<i32>>) -> i32 {
-    input.iter().map(|series| extrapolate(&series.iter().rev().cloned().collect())).sum()
-}
\ No newline at end of file
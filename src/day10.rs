@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
 
 // I really wanted to stick with usize, but oh well!
 type Point = (i32, i32);
@@ -12,6 +13,9 @@ pub enum Tile {
     PipeEW,
     PipeNS,
     Start,
+    Ground,
+    // Anything else is kept as it was, so the map can be written back out unchanged
+    Other(char),
 }
 
 impl Tile {
@@ -23,30 +27,122 @@ impl Tile {
             Tile::BendSW => "SW",
             Tile::PipeEW => "EW",
             Tile::PipeNS => "NS",
-            Tile::Start  => "",
+            _            => "",
         };
 
         name.contains(c)
     }
+
+    pub fn box_char(self) -> char {
+        match self {
+            Tile::BendNE => '└',
+            Tile::BendNW => '┘',
+            Tile::BendSE => '┌',
+            Tile::BendSW => '┐',
+            Tile::PipeEW => '─',
+            Tile::PipeNS => '│',
+            Tile::Start  => 'S',
+            Tile::Ground => '·',
+            Tile::Other(c) => c,
+        }
+    }
+
+    pub fn to_char(self) -> char {
+        match self {
+            Tile::BendNE => 'L',
+            Tile::BendNW => 'J',
+            Tile::BendSE => 'F',
+            Tile::BendSW => '7',
+            Tile::PipeEW => '-',
+            Tile::PipeNS => '|',
+            Tile::Start  => 'S',
+            Tile::Ground => '.',
+            Tile::Other(c) => c,
+        }
+    }
+
+    pub fn is_pipe(self) -> bool {
+        !self.exits().is_empty()
+    }
+
+    pub fn connects(self, dir: Direction) -> bool {
+        self.contains_char(dir.letter())
+    }
+
+    pub fn exits(self) -> Vec<Direction> {
+        Direction::ALL
+            .into_iter()
+            .filter(|&dir| self.connects(dir))
+            .collect()
+    }
+
+    pub fn from_exits(first: Direction, second: Direction) -> Tile {
+        [Tile::BendNE, Tile::BendNW, Tile::BendSE, Tile::BendSW, Tile::PipeEW, Tile::PipeNS]
+            .into_iter()
+            .find(|tile| first != second && tile.connects(first) && tile.connects(second))
+            .unwrap()
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Direction {
     North,
     East,
     South,
     West,
-    Stop,
 }
 
 impl Direction {
+    pub const ALL: [Direction; 4] = [Direction::North, Direction::East, Direction::South, Direction::West];
+
     pub fn from(self, loc: &Point) -> Point {
         match self {
             Direction::North => (loc.0, loc.1 - 1),
             Direction::East  => (loc.0 + 1, loc.1),
             Direction::South => (loc.0, loc.1 + 1),
             Direction::West  => (loc.0 - 1, loc.1),
-            Direction::Stop  => (loc.0, loc.1),
+        }
+    }
+
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::North => Direction::South,
+            Direction::East  => Direction::West,
+            Direction::South => Direction::North,
+            Direction::West  => Direction::East,
+        }
+    }
+
+    fn letter(self) -> char {
+        match self {
+            Direction::North => 'N',
+            Direction::East  => 'E',
+            Direction::South => 'S',
+            Direction::West  => 'W',
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MazeError {
+    NoStart,
+    // The start needs exactly two neighbours pointing into it for its shape to be known
+    AmbiguousStart(Vec<Direction>),
+    // A pipe on the loop leads somewhere that doesn't lead back
+    Dangling(Point),
+    // An interior method found a different set of tiles from the others
+    Disagreement(Method),
+}
+
+impl fmt::Display for MazeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MazeError::NoStart => write!(f, "There's no start tile"),
+            MazeError::AmbiguousStart(dirs) => {
+                write!(f, "The start connects {:?}, which isn't exactly two ways", dirs)
+            },
+            MazeError::Dangling(pos) => write!(f, "The pipe at {:?} doesn't connect back", pos),
+            MazeError::Disagreement(method) => write!(f, "{:?} disagrees with the other methods", method),
         }
     }
 }
@@ -61,7 +157,7 @@ impl Area for Vec<Point> {
             .windows(2)
             .fold(0, |acc, matrix|
                 acc + (matrix[0].0 * matrix[1].1) - (matrix[1].0 * matrix[0].1)
-            ) / 2) as usize
+            ) / 2).unsigned_abs() as usize
      }
 }
 
@@ -75,164 +171,353 @@ impl Interior for usize {
     }
 }
 
-fn find_next(map: &HashMap<Point, Tile>, current: Point, last: Option<Direction>) -> Point {
-    let mut next = None;
-    
-    if last.is_none() {
-        let check = &[
-            (current.0, current.1 - 1, 'S'),
-            (current.0 + 1, current.1, 'W'),
-            (current.0, current.1 + 1, 'N'),
-            (current.0 - 1, current.1, 'E'),
-        ];
-
-        for &(col, row, dir) in check {
-            if let Some(tile) = map.get(&(col, row)) {
-                if tile.contains_char(dir) {
-                    next = Some((col, row));
-                    break;
-                }
+// Ways of finding the tiles enclosed by the loop, which should all agree
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    // Sweep each row, flipping between outside and inside at every crossing of the loop
+    Scanline,
+    // Blow each tile up to 3x3 so there are gaps to squeeze between pipes, then flood the outside
+    FloodFill,
+    // Count how many times the loop winds around each tile
+    Winding,
+}
+
+// Every character of the map is kept, so it can be written back out exactly as it was read in.
+// Whitespace either side of a row isn't part of the grid, but it's held on to for writing out.
+#[derive(Debug)]
+pub struct PipeMaze {
+    pub tiles: HashMap<Point, Tile>,
+    pub start: Point,
+    // The pipe hiding under the start
+    pub start_tile: Tile,
+    pub width: i32,
+    pub height: i32,
+    margins: Vec<(String, String)>,
+    final_newline: bool,
+}
+
+impl PipeMaze {
+    pub fn parse(input: &str) -> Result<PipeMaze, MazeError> {
+        let mut tiles = HashMap::new();
+        let mut start = None;
+        let (mut width, mut height) = (0, 0);
+        let mut margins = Vec::new();
+        for (row, line) in input.lines().enumerate() {
+            let trimmed = line.trim();
+            let lead = line.len() - line.trim_start().len();
+            margins.push((line[..lead].to_string(), line[lead + trimmed.len()..].to_string()));
+            height = row as i32 + 1;
+            width = width.max(trimmed.chars().count() as i32);
+            for (col, char) in trimmed.chars().enumerate() {
+                let tile = match char {
+                    'L' => Tile::BendNE,
+                    'J' => Tile::BendNW,
+                    'F' => Tile::BendSE,
+                    '7' => Tile::BendSW,
+                    '-' => Tile::PipeEW,
+                    '|' => Tile::PipeNS,
+                    'S' => {
+                        start = Some((col as i32, row as i32));
+                        Tile::Start
+                    },
+                    '.' => Tile::Ground,
+                    c   => Tile::Other(c),
+                };
+                tiles.insert((col as i32, row as i32), tile);
             }
         }
-    } else {
-        next = match map.get(&current).unwrap() {
-            Tile::BendNE => {
-                match last.unwrap() {
-                    Direction::South => Some(Direction::East.from(&current)),
-                    Direction::West  => Some(Direction::North.from(&current)),
-                    _ => panic!("Invalid move."),
-                }
-            },
-            Tile::BendNW => {
-                match last.unwrap() {
-                    Direction::South => Some(Direction::West.from(&current)),
-                    Direction::East  => Some(Direction::North.from(&current)),
-                    _ => panic!("Invalid move."),
-                }
-            },
-            Tile::BendSE => {
-                match last.unwrap() {
-                    Direction::North => Some(Direction::East.from(&current)),
-                    Direction::West  => Some(Direction::South.from(&current)),
-                    _ => panic!("Invalid move."),
+        let start = start.ok_or(MazeError::NoStart)?;
+
+        let exits: Vec<Direction> = Direction::ALL
+            .into_iter()
+            .filter(|dir| {
+                tiles
+                    .get(&dir.from(&start))
+                    .is_some_and(|tile| tile.connects(dir.opposite()))
+            })
+            .collect();
+        if exits.len() != 2 {
+            return Err(MazeError::AmbiguousStart(exits));
+        }
+        let start_tile = Tile::from_exits(exits[0], exits[1]);
+        tiles.insert(start, start_tile);
+
+        Ok(PipeMaze {
+            tiles,
+            start,
+            start_tile,
+            width,
+            height,
+            margins,
+            final_newline: input.ends_with('\n'),
+        })
+    }
+
+    // Every pipe with an end that isn't met by a neighbouring pipe
+    pub fn dangling(&self) -> Vec<Point> {
+        let mut dangling: Vec<Point> = self.tiles
+            .iter()
+            .filter(|(pos, tile)| {
+                tile.exits().into_iter().any(|dir| {
+                    !self.tiles
+                        .get(&dir.from(pos))
+                        .is_some_and(|next| next.connects(dir.opposite()))
+                })
+            })
+            .map(|(&pos, _)| pos)
+            .collect();
+        dangling.sort_unstable_by_key(|&(col, row)| (row, col));
+
+        dangling
+    }
+
+    // Walks the loop through the start, returning each tile once in the order visited
+    pub fn main_loop(&self) -> Result<Vec<Point>, MazeError> {
+        let mut visited = vec![self.start];
+        let mut dir = self.start_tile.exits()[0];
+        let mut current = dir.from(&self.start);
+
+        while current != self.start {
+            let tile = self.tiles
+                .get(&current)
+                .filter(|tile| tile.connects(dir.opposite()))
+                .ok_or(MazeError::Dangling(visited[visited.len() - 1]))?;
+            visited.push(current);
+            dir = tile.exits().into_iter().find(|&exit| exit != dir.opposite()).unwrap();
+            current = dir.from(&current);
+        }
+
+        Ok(visited)
+    }
+
+    fn points(&self) -> impl Iterator<Item = Point> + '_ {
+        (0..self.height).flat_map(move |row| (0..self.width).map(move |col| (col, row)))
+    }
+
+    pub fn interior(&self, method: Method) -> Result<HashSet<Point>, MazeError> {
+        let path = self.main_loop()?;
+        let on_loop: HashSet<Point> = path.iter().copied().collect();
+
+        Ok(match method {
+            Method::Scanline  => self.scanline(&on_loop),
+            Method::FloodFill => self.flood_fill(&on_loop),
+            Method::Winding   => self.winding(&path, &on_loop),
+        })
+    }
+
+    // Runs every method and checks they all found the same tiles, and as many as Pick's theorem
+    pub fn check_interior(&self) -> Result<HashSet<Point>, MazeError> {
+        let inside = self.interior(Method::Scanline)?;
+        for method in [Method::FloodFill, Method::Winding] {
+            if self.interior(method)? != inside {
+                return Err(MazeError::Disagreement(method));
+            }
+        }
+        if inside.len() != solve_part2(self) {
+            return Err(MazeError::Disagreement(Method::Scanline));
+        }
+
+        Ok(inside)
+    }
+
+    // A | always crosses the loop, as does F---J or L---7, but F---7 and L---J just graze it
+    fn scanline(&self, on_loop: &HashSet<Point>) -> HashSet<Point> {
+        let mut inside = HashSet::new();
+        for row in 0..self.height {
+            let mut crossed = false;
+            let mut opened = None;
+            for col in 0..self.width {
+                if !on_loop.contains(&(col, row)) {
+                    if crossed {
+                        inside.insert((col, row));
+                    }
+                    continue;
                 }
-            },
-            Tile::BendSW => {
-                match last.unwrap() {
-                    Direction::North => Some(Direction::West.from(&current)),
-                    Direction::East  => Some(Direction::South.from(&current)),
-                    _ => panic!("Invalid move."),
+                match self.tiles[&(col, row)] {
+                    Tile::PipeNS => crossed = !crossed,
+                    bend @ (Tile::BendSE | Tile::BendNE) => opened = Some(bend),
+                    Tile::BendNW => crossed ^= opened == Some(Tile::BendSE),
+                    Tile::BendSW => crossed ^= opened == Some(Tile::BendNE),
+                    _ => {},
                 }
-            },
-            Tile::PipeEW => {
-                match last.unwrap() {
-                    Direction::East => Some(Direction::East.from(&current)),
-                    Direction::West => Some(Direction::West.from(&current)),
-                    _ => panic!("Invalid move."),
+            }
+        }
+
+        inside
+    }
+
+    // The scaled grid has a border of one cell all round, so the flood can get everywhere outside
+    fn flood_fill(&self, on_loop: &HashSet<Point>) -> HashSet<Point> {
+        let (width, height) = (self.width * 3 + 2, self.height * 3 + 2);
+        let cell = |(col, row): Point| (row * width + col) as usize;
+        let mut blocked = vec![false; (width * height) as usize];
+        for &pos in on_loop {
+            let centre = (pos.0 * 3 + 2, pos.1 * 3 + 2);
+            blocked[cell(centre)] = true;
+            for dir in self.tiles[&pos].exits() {
+                blocked[cell(dir.from(&centre))] = true;
+            }
+        }
+
+        let mut flooded = vec![false; blocked.len()];
+        let mut queue = VecDeque::from([(0, 0)]);
+        flooded[0] = true;
+        while let Some(pos) = queue.pop_front() {
+            for dir in Direction::ALL {
+                let next = dir.from(&pos);
+                if next.0 < 0 || next.1 < 0 || next.0 >= width || next.1 >= height {
+                    continue;
                 }
-            },
-            Tile::PipeNS => {
-                match last.unwrap() {
-                    Direction::North => Some(Direction::North.from(&current)),
-                    Direction::South => Some(Direction::South.from(&current)),
-                    _ => panic!("Invalid move."),
+                if !blocked[cell(next)] && !flooded[cell(next)] {
+                    flooded[cell(next)] = true;
+                    queue.push_back(next);
                 }
-            },
-            Tile::Start => Some(Direction::Stop.from(&current)),
-        };
+            }
+        }
+
+        self.points()
+            .filter(|pos| !on_loop.contains(pos) && !flooded[cell((pos.0 * 3 + 2, pos.1 * 3 + 2))])
+            .collect()
     }
 
-    next.unwrap()
-}
+    // Sums the signed crossings of a ray running east from each tile. The ray is nudged a hair
+    // south, so it crosses each vertical step of the loop between this row and the next exactly once
+    // and never runs along a horizontal one.
+    fn winding(&self, path: &[Point], on_loop: &HashSet<Point>) -> HashSet<Point> {
+        let mut crossings: HashMap<i32, Vec<(i32, i32)>> = HashMap::new();
+        for (idx, &from) in path.iter().enumerate() {
+            let to = path[(idx + 1) % path.len()];
+            if from.0 == to.0 {
+                crossings
+                    .entry(from.1.min(to.1))
+                    .or_default()
+                    .push((from.0, to.1 - from.1));
+            }
+        }
 
-#[aoc_generator(day10)]
-pub fn input_generator(input: &str) -> (HashMap<Point, Tile>, Point) {
-    input
-        .lines()
-        .enumerate()
-        .fold((HashMap::new(), (0, 0)), |(mut map, mut start), (row, line)| {
-            line.trim()
-                .chars()
-                .enumerate()
-                .for_each(|(col, char)| {
-                    let tile = match char {
-                        'L' => Tile::BendNE,
-                        'J' => Tile::BendNW,
-                        'F' => Tile::BendSE,
-                        '7' => Tile::BendSW,
-                        '-' => Tile::PipeEW,
-                        '|' => Tile::PipeNS,
-                        'S' => {
-                            start = (col as i32, row as i32);
-                            Tile::Start
-                        },
-                        _   => return,
-                    };
-                    map.insert((col as i32, row as i32), tile);
+        self.points()
+            .filter(|pos| !on_loop.contains(pos))
+            .filter(|pos| {
+                crossings.get(&pos.1).is_some_and(|row| {
+                    row.iter().filter(|&&(col, _)| col > pos.0).map(|&(_, sign)| sign).sum::<i32>() != 0
+                })
+            })
+            .collect()
+    }
+
+    // Draws the loop with box-drawing characters and marks every other tile I(nside) or O(utside)
+    pub fn render_interior(&self, inside: &HashSet<Point>) -> String {
+        let on_loop: HashSet<Point> = self.main_loop().unwrap_or_default().into_iter().collect();
+        let mut render = String::new();
+        for row in 0..self.height {
+            for col in 0..self.width {
+                render.push(if on_loop.contains(&(col, row)) {
+                    self.tiles[&(col, row)].box_char()
+                } else if inside.contains(&(col, row)) {
+                    'I'
+                } else {
+                    'O'
                 });
-                (map, start)
+            }
+            render.push('\n');
+        }
+
+        render
+    }
+
+    fn write_rows(&self, mut draw: impl FnMut(Point, Tile) -> String) -> String {
+        let mut out = String::new();
+        for (row, (lead, trail)) in (0..self.height).zip(&self.margins) {
+            if row > 0 {
+                out.push('\n');
+            }
+            out += lead;
+            let mut col = 0;
+            while let Some(&tile) = self.tiles.get(&(col, row)) {
+                out += &draw((col, row), tile);
+                col += 1;
+            }
+            out += trail;
+        }
+        if self.final_newline {
+            out.push('\n');
+        }
+
+        out
+    }
+
+    // Draws the maze with box-drawing characters, with the loop in bold yellow and junk pipes dimmed
+    pub fn render(&self) -> String {
+        let on_loop: HashSet<Point> = self.main_loop().unwrap_or_default().into_iter().collect();
+        self.write_rows(|pos, tile| {
+            if on_loop.contains(&pos) {
+                format!("\x1b[1;33m{}\x1b[0m", tile.box_char())
+            } else if tile.is_pipe() {
+                format!("\x1b[2m{}\x1b[0m", tile.box_char())
+            } else {
+                tile.box_char().to_string()
+            }
         })
+    }
+
+    // The original map with every tile off the loop swapped for ground
+    pub fn clean(&self) -> String {
+        let on_loop: HashSet<Point> = self.main_loop().unwrap_or_default().into_iter().collect();
+        self.write_rows(|pos, tile| {
+            if pos == self.start {
+                'S'.to_string()
+            } else if on_loop.contains(&pos) {
+                tile.to_char().to_string()
+            } else {
+                '.'.to_string()
+            }
+        })
+    }
 }
 
-#[aoc(day10, part1)]
-pub fn solve_part1(input: &(HashMap<Point, Tile>, Point)) -> usize {
-    let mut last_move = None;
-    let start = input.1;
-    let mut current = start;
-    let mut visited: Vec<Point> = Vec::new();
-
-    loop {
-        let next = find_next(&input.0, current, last_move);
-        visited.push(next);
-        let delta = (next.0 - current.0, next.1 - current.1);
-        last_move = match delta {
-            (0, -1) => Some(Direction::North),
-            (1, 0)  => Some(Direction::East),
-            (0, 1)  => Some(Direction::South),
-            (-1, 0) => Some(Direction::West),
-            (0, 0)  => break,
-            _ => panic!("Invalid move {:?}.", delta)
-        };
-        current = next;
+impl fmt::Display for PipeMaze {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let map = self.write_rows(|pos, tile| {
+            if pos == self.start { 'S' } else { tile.to_char() }.to_string()
+        });
+        write!(f, "{}", map)
     }
-    
-    visited.len() / 2
 }
 
+#[aoc_generator(day10)]
+pub fn input_generator(input: &str) -> PipeMaze {
+    PipeMaze::parse(input).unwrap_or_else(|err| panic!("{err}"))
+}
+
+#[aoc(day10, part1)]
+pub fn solve_part1(input: &PipeMaze) -> usize {
+    input.main_loop().unwrap_or_else(|err| panic!("{err}")).len() / 2
+}
+
+// Apparently, calculating the area using all +13k vertices is still faster than using an optimised
+// vertex table of only the start point and bends.
 #[aoc(day10, part2)]
-pub fn solve_part2(input: &(HashMap<Point, Tile>, Point)) -> usize {
-    let mut last_move = None;
-    let start = input.1;
-    let mut current = start;
-    // let mut vertices: Vec<Point> = vec![start];
-    // let mut visited: Vec<Point> = Vec::new();
-    let mut visited: Vec<Point> = vec![start];
-
-    loop {
-        let next = find_next(&input.0, current, last_move);
-
-        // Apparently, calculating the area using all +13k vertices is still faster than using an optimised
-        // vertex table of only the start point and bends.
-        // if let Some(&tile) = input.0.get(&next) {
-        //     if !(tile == Tile::PipeEW || tile == Tile::PipeNS) {
-        //         vertices.push(next);
-        //     }
-        // }
-
-        visited.push(next);
-        let delta = (next.0 - current.0, next.1 - current.1);
-        last_move = match delta {
-            (0, -1) => Some(Direction::North),
-            (1, 0)  => Some(Direction::East),
-            (0, 1)  => Some(Direction::South),
-            (-1, 0) => Some(Direction::West),
-            (0, 0)  => break,
-            _ => panic!("Invalid move {:?}.", delta)
-        };
-        current = next;
-    }
-    // vertices.shoestring().picks(visited.len())
-    visited.shoestring().picks(visited.len() - 1)
+pub fn solve_part2(input: &PipeMaze) -> usize {
+    let mut vertices = input.main_loop().unwrap_or_else(|err| panic!("{err}"));
+    let boundary = vertices.len();
+    vertices.push(input.start);
+
+    vertices.shoestring().picks(boundary)
+}
+
+#[aoc(day10, part2, scanline)]
+pub fn solve_part2_scanline(input: &PipeMaze) -> usize {
+    input.interior(Method::Scanline).unwrap_or_else(|err| panic!("{err}")).len()
+}
+
+#[aoc(day10, part2, flood_fill)]
+pub fn solve_part2_flood_fill(input: &PipeMaze) -> usize {
+    input.interior(Method::FloodFill).unwrap_or_else(|err| panic!("{err}")).len()
+}
+
+#[aoc(day10, part2, winding)]
+pub fn solve_part2_winding(input: &PipeMaze) -> usize {
+    input.interior(Method::Winding).unwrap_or_else(|err| panic!("{err}")).len()
 }
 
 #[cfg(test)]
@@ -250,7 +535,29 @@ mod tests {
                         SJ.L7
                         |F--J
                         LJ...";
-    
+
+    const TEST3: &str = ".F----7F7F7F7F-7....
+                        .|F--7||||||||FJ....
+                        .||.FJ||||||||L7....
+                        FJL7L7LJLJ||LJ.L-7..
+                        L--J.L7...LJS7F-7L7.
+                        ....F-J..F7FJ|L7L7L7
+                        ....L7.F7||L7|.L7L7|
+                        .....|FJLJ|FJ|F7|.LJ
+                        ....FJL-7.||.||||...
+                        ....L---J.LJ.LJLJ...";
+
+    const TEST4: &str = "FF7FSF7F7F7F7F7F---7
+                        L|LJ||||||||||||F--J
+                        FL-7LJLJ||||||LJL-77
+                        F--JF--7||LJLJ7F7FJ-
+                        L---JF-JLJ.||-FJLJJ7
+                        |F|F-JF---7F7-L7L|7|
+                        |FFJF7L7F-JF7|JL---7
+                        7-L-JL7||F7|L7F-7F7|
+                        L.L7LFJ|||||FJL7||LJ
+                        L7JLJL-JLJLJL--JLJ.L";
+
     #[test]
     fn part1_test1() {
         assert_eq!(solve_part1(&input_generator(TEST1)), 4);
@@ -270,4 +577,60 @@ mod tests {
     fn part2_test2() {
         assert_eq!(solve_part2(&input_generator(TEST2)), 1);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn maze() {
+        let maze = input_generator(TEST1);
+        assert_eq!(maze.start_tile, Tile::BendSE);
+        assert_eq!(maze.main_loop().unwrap()[..3], [(1, 1), (2, 1), (3, 1)]);
+        assert_eq!(input_generator(TEST2).start_tile, Tile::BendSE);
+        assert!(maze.dangling().is_empty());
+
+        let junk = PipeMaze::parse("-L|F7\n7S-7|\nL|7||\n-L-J|\nL|-JF").unwrap();
+        assert_eq!(junk.main_loop().unwrap().len(), 8);
+        assert!(junk.dangling().contains(&(0, 0)));
+        assert!(!junk.dangling().contains(&(1, 1)));
+
+        assert_eq!(PipeMaze::parse(".S.\n.|.").unwrap_err(), MazeError::AmbiguousStart(vec![Direction::South]));
+        assert_eq!(
+            PipeMaze::parse(".|.\n-S-\n.|.").unwrap_err(),
+            MazeError::AmbiguousStart(Direction::ALL.to_vec())
+        );
+        assert_eq!(PipeMaze::parse("S7\n|.").unwrap().main_loop(), Err(MazeError::Dangling((1, 0))));
+        assert_eq!(PipeMaze::parse("...").unwrap_err(), MazeError::NoStart);
+    }
+
+    #[test]
+    fn interior_methods() {
+        for (test, count) in [(TEST1, 1), (TEST2, 1), (TEST3, 8), (TEST4, 10)] {
+            let maze = input_generator(test);
+            assert_eq!(maze.check_interior().unwrap().len(), count);
+            assert_eq!(solve_part2(&maze), count);
+        }
+        let maze = input_generator(TEST1);
+        assert_eq!(maze.interior(Method::Winding).unwrap(), HashSet::from([(2, 2)]));
+        assert_eq!(maze.render_interior(&HashSet::from([(2, 2)])), "OOOOO\nO┌─┐O\nO│I│O\nO└─┘O\nOOOOO\n");
+    }
+
+    #[test]
+    fn round_trip() {
+        let raw = "-L|F7\n7S-7|\nL|7||\n-L-J|\nL|-JF\n";
+        let maze = PipeMaze::parse(raw).unwrap();
+        assert_eq!(maze.to_string(), raw);
+        assert_eq!(maze.clean(), ".....\n.S-7.\n.|.|.\n.L-J.\n.....\n");
+        assert_eq!(PipeMaze::parse(&maze.clean()).unwrap().to_string(), maze.clean());
+        assert!(maze.render().starts_with("\x1b[2m─\x1b[0m\x1b[2m└\x1b[0m"));
+        assert!(maze.render().contains("\x1b[1;33m┌\x1b[0m"));
+
+        // Indented like the test inputs, with no newline at the end and a wide character in it
+        let raw = "..F7.
+                   .FJ|é
+                   SJ.L7
+                   |F--J
+                   LJ...  ";
+        let maze = PipeMaze::parse(raw).unwrap();
+        assert_eq!(maze.to_string(), raw);
+        assert_eq!(maze.width, 5);
+        assert_eq!(maze.tiles[&(4, 1)], Tile::Other('é'));
+    }
+}
@@ -1,21 +1,151 @@
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+
 type Point = (usize, usize);
 
+// How wide each column and how tall each row becomes. Empty columns grow by `x_coef` and empty
+// rows by `y_coef`, while everything else stays 1, unless a line has a factor of its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expansion {
+    pub x_coef: u128,
+    pub y_coef: u128,
+    pub cols: HashMap<usize, u128>,
+    pub rows: HashMap<usize, u128>,
+}
+
+impl Expansion {
+    pub fn new(x_coef: u128, y_coef: u128) -> Self {
+        Expansion { x_coef, y_coef, cols: HashMap::new(), rows: HashMap::new() }
+    }
+
+    pub fn uniform(coef: usize) -> Self {
+        Expansion::new(coef as u128, coef as u128)
+    }
+
+    pub fn col(mut self, col: usize, factor: u128) -> Self {
+        self.cols.insert(col, factor);
+        self
+    }
+
+    pub fn row(mut self, row: usize, factor: u128) -> Self {
+        self.rows.insert(row, factor);
+        self
+    }
+}
+
+// Start of each line once the lines before it have been resized, with one extra entry for the
+// far edge. Every line is one of `len`, `empty` is in order, and `explicit` overrides the rest.
+fn offsets(len: usize, empty: &[usize], coef: u128, explicit: &HashMap<usize, u128>) -> Vec<u128> {
+    let mut offsets = vec![0; len + 1];
+    for line in 0..len {
+        let factor = explicit
+            .get(&line)
+            .copied()
+            .unwrap_or(if empty.binary_search(&line).is_ok() { coef } else { 1 });
+        offsets[line + 1] = offsets[line] + factor;
+    }
+
+    offsets
+}
+
 #[derive(Debug)]
 pub struct SkyMap {
     pub map: Vec<Point>,
     pub empty_col: Vec<usize>,
     pub empty_row: Vec<usize>,
+    pub width: usize,
+    pub height: usize,
 }
 
 impl SkyMap {
-    fn total_manhattan_distances(&self, coef: usize) -> usize {
-        (0..self.map.len() - 1)
+    // Where every galaxy ends up after expansion
+    pub fn positions(&self, expansion: &Expansion) -> Vec<(u128, u128)> {
+        let cols = offsets(self.width, &self.empty_col, expansion.x_coef, &expansion.cols);
+        let rows = offsets(self.height, &self.empty_row, expansion.y_coef, &expansion.rows);
+
+        self.map.iter().map(|&(col, row)| (cols[col], rows[row])).collect()
+    }
+
+    pub fn total_manhattan_distances(&self, coef: usize) -> u128 {
+        self.total_distance(&Expansion::uniform(coef))
+    }
+
+    // The distance between two galaxies splits into a column part and a row part, and each axis
+    // can be summed on its own. Once the positions along an axis are sorted, the k-th one is the
+    // far end of k pairs whose near ends are all in the running prefix sum. That's O(n log n)
+    // rather than checking each of the O(n²) pairs.
+    pub fn total_distance(&self, expansion: &Expansion) -> u128 {
+        let (cols, rows) = self.positions(expansion).into_iter().unzip();
+
+        pairwise_sum(cols) + pairwise_sum(rows)
+    }
+
+    // The closest other galaxy to galaxy `idx` and how far away it is, or None if there's no
+    // such galaxy or no other one
+    pub fn nearest(&self, idx: usize, expansion: &Expansion) -> Option<(usize, u128)> {
+        self.distances_from(idx, expansion)?.min_by_key(|&(_, dist)| dist)
+    }
+
+    pub fn farthest(&self, idx: usize, expansion: &Expansion) -> Option<(usize, u128)> {
+        self.distances_from(idx, expansion)?.max_by_key(|&(_, dist)| dist)
+    }
+
+    fn distances_from(&self, idx: usize, expansion: &Expansion) -> Option<impl Iterator<Item = (usize, u128)>> {
+        let positions = self.positions(expansion);
+        let from = *positions.get(idx)?;
+
+        Some(positions
+            .into_iter()
+            .enumerate()
+            .filter(move |&(other, _)| other != idx)
+            .map(move |(other, pos)| (other, from.0.abs_diff(pos.0) + from.1.abs_diff(pos.1))))
+    }
+
+    fn pairs(positions: &[(u128, u128)]) -> impl Iterator<Item = (usize, usize, u128)> + '_ {
+        positions.iter().enumerate().flat_map(move |(i, a)| {
+            positions[i + 1..]
+                .iter()
+                .enumerate()
+                .map(move |(offset, b)| (i, i + offset + 1, a.0.abs_diff(b.0) + a.1.abs_diff(b.1)))
+        })
+    }
+
+    // How many pairs of galaxies there are at each distance
+    pub fn histogram(&self, expansion: &Expansion) -> BTreeMap<u128, usize> {
+        let mut histogram = BTreeMap::new();
+        for (_, _, dist) in Self::pairs(&self.positions(expansion)) {
+            *histogram.entry(dist).or_insert(0) += 1;
+        }
+
+        histogram
+    }
+
+    // The k closest pairs, nearest first. A max-heap holds the best k seen so far, so the
+    // furthest of them is the one to drop when a closer pair turns up. There are only so many
+    // pairs, so a bigger k is no different to asking for all of them.
+    pub fn closest_pairs(&self, k: usize, expansion: &Expansion) -> Vec<(usize, usize, u128)> {
+        let n = self.map.len();
+        let k = k.min(n * n.saturating_sub(1) / 2);
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        for (i, j, dist) in Self::pairs(&self.positions(expansion)) {
+            heap.push((dist, i, j));
+            if heap.len() > k {
+                heap.pop();
+            }
+        }
+
+        heap.into_sorted_vec().into_iter().map(|(dist, i, j)| (i, j, dist)).collect()
+    }
+
+    // The original pair-by-pair sum, kept to check the fast one against
+    #[cfg(test)]
+    fn total_manhattan_distances_pairwise(&self, coef: usize) -> u128 {
+        (0..self.map.len().saturating_sub(1))
             .flat_map(|i| (i + 1..self.map.len()).map(move |j| (self.map[i], self.map[j])))
-            .map(|(first, other)| self.dist_with_coef(first, other, coef))
+            .map(|(first, other)| self.dist_with_coef(first, other, coef) as u128)
             .sum()
     }
 
-    fn dist_with_coef(&self, first: Point, other: Point, coef: usize) -> usize {
+    pub fn dist_with_coef(&self, first: Point, other: Point, coef: usize) -> usize {
         let (col_min, col_max) = (first.0.min(other.0), first.0.max(other.0));
         let (row_min, row_max) = (first.1.min(other.1), first.1.max(other.1));
 
@@ -25,16 +155,34 @@ impl SkyMap {
         let col_exp = self.empty_col.iter().filter(|&col| *col >= col_min && *col <= col_max).count();
         let row_exp = self.empty_row.iter().filter(|&row| *row >= row_min && *row <= row_max).count();
     
-        delta_col + col_exp * (coef - 1) + delta_row + row_exp * (coef - 1)
+        // Adding before taking away keeps a coefficient of 0 from underflowing
+        delta_col + col_exp * coef - col_exp + delta_row + row_exp * coef - row_exp
     }
 }
 
+// Sum of the differences between every pair of positions
+fn pairwise_sum(mut coords: Vec<u128>) -> u128 {
+    coords.sort_unstable();
+    let mut prefix = 0;
+
+    coords
+        .iter()
+        .enumerate()
+        .map(|(k, &coord)| {
+            let dist = coord * k as u128 - prefix;
+            prefix += coord;
+            dist
+        })
+        .sum()
+}
+
 #[aoc_generator(day11)]
 pub fn input_generator(input: &str) -> SkyMap {
     let mut empty_col: Vec<usize> = Vec::new();
     let mut empty_row: Vec<usize> = Vec::new();
     let mut map: Vec<Point> = Vec::new();
-    let width = input.lines().last().unwrap().trim().len();
+    let width = input.lines().map(|line| line.trim().len()).max().unwrap_or(0);
+    let mut occupied = vec![false; width];
 
     input
         .lines()
@@ -47,6 +195,7 @@ pub fn input_generator(input: &str) -> SkyMap {
                 .for_each(|(col, char)| {
                     if char == '#' {
                         map.push((col, row));
+                        occupied[col] = true;
                         row_count += 1;
                     }
                 });
@@ -56,27 +205,31 @@ pub fn input_generator(input: &str) -> SkyMap {
         });
     
     (0..width).for_each(|i| {
-        if !map.iter().any(|&(col, _)| col == i) {
+        if !occupied[i] {
             empty_col.push(i);
         }
     });
 
-    SkyMap { map, empty_col, empty_row }
+    let height = input.lines().count();
+
+    SkyMap { map, empty_col, empty_row, width, height }
 }
 
 #[aoc(day11, part1)]
-pub fn solve_part1(input: &SkyMap) -> usize {
+pub fn solve_part1(input: &SkyMap) -> u128 {
     input.total_manhattan_distances(2)
 }
 
 #[aoc(day11, part2)]
-pub fn solve_part2(input: &SkyMap) -> usize {
+pub fn solve_part2(input: &SkyMap) -> u128 {
     input.total_manhattan_distances(1_000_000)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::time::Instant;
 
     const TEST: &str = "...#......
                         .......#..
@@ -100,4 +253,78 @@ mod tests {
     fn part1_test() {
         assert_eq!(solve_part1(&input_generator(TEST)), 374);
     }
-}
\ No newline at end of file
+
+    // Scatters galaxies over a square sky, leaving every `gap`-th row and column empty along with
+    // any others the galaxies happen to miss
+    fn random_sky(rng: &mut StdRng, galaxies: usize, size: usize, gap: usize) -> SkyMap {
+        let mut line = || loop {
+            let idx = rng.gen_range(0..size);
+            if idx % gap != 0 {
+                break idx;
+            }
+        };
+        let mut sky = vec![vec!['.'; size]; size];
+        for _ in 0..galaxies {
+            let row = line();
+            sky[row][line()] = '#';
+        }
+        let rows: Vec<String> = sky.into_iter().map(|line| line.into_iter().collect()).collect();
+        input_generator(&rows.join("\n"))
+    }
+
+    #[test]
+    fn expansion_coefficients() {
+        let input = input_generator(TEST);
+        assert_eq!(input.total_manhattan_distances(10), 1030);
+        assert_eq!(input.total_manhattan_distances(100), 8410);
+        let mut rng = StdRng::seed_from_u64(11);
+        for _ in 0..20 {
+            let sky = random_sky(&mut rng, 60, 40, 7);
+            for coef in [0, 1, 2, 7, 1_000_000] {
+                assert_eq!(sky.total_manhattan_distances(coef), sky.total_manhattan_distances_pairwise(coef));
+            }
+        }
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn expansion_bench() {
+        let sky = random_sky(&mut StdRng::seed_from_u64(11), 20_000, 2_000, 4);
+        assert_eq!((sky.empty_col.len(), sky.empty_row.len()), (500, 500));
+        let expansion = Expansion::uniform(1_000_000);
+        let timer = Instant::now();
+        let total = sky.total_distance(&expansion);
+        let sorted = timer.elapsed();
+        let timer = Instant::now();
+        let expected = SkyMap::pairs(&sky.positions(&expansion)).map(|(_, _, dist)| dist).sum();
+        let pairwise = timer.elapsed();
+        assert_eq!(total, expected);
+        println!("{} galaxies, sorted: {:?}, pairwise: {:?}", sky.map.len(), sorted, pairwise);
+    }
+
+    #[test]
+    fn queries() {
+        let input = input_generator(TEST);
+        let double = Expansion::uniform(2);
+        assert_eq!(input.nearest(4, &double), Some((2, 5)));
+        assert_eq!(input.farthest(4, &double), Some((1, 13)));
+        assert_eq!(input.histogram(&double).values().sum::<usize>(), 36);
+        assert_eq!(input.histogram(&double).iter().next_back(), Some((&19, &1)));
+        assert_eq!(input.closest_pairs(1, &double), vec![(1, 3, 5)]);
+        assert_eq!(input.closest_pairs(3, &double).len(), 3);
+        assert_eq!(input.closest_pairs(usize::MAX, &double).len(), 36);
+        assert_eq!(input.nearest(9, &double), None);
+        assert_eq!(input.farthest(usize::MAX, &double), None);
+        assert_eq!(input_generator("..#").nearest(0, &double), None);
+
+        // Only the columns grow, and a row full of galaxies can be stretched as well
+        assert_eq!(input.positions(&Expansion::new(2, 1))[3], (8, 4));
+        let stretched = Expansion::new(1, 1).row(0, 5);
+        assert_eq!(input.positions(&stretched)[..2], [(3, 0), (7, 5)]);
+        assert_eq!(
+            input.total_distance(&Expansion::new(3, 3)),
+            input.total_manhattan_distances(3)
+        );
+    }
+}
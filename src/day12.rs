@@ -1,3 +1,5 @@
+use num::{BigUint, CheckedAdd, One, Zero};
+use rand::Rng;
 use rayon::prelude::*;
 use std::collections::HashMap;
 use std::iter::repeat;
@@ -12,22 +14,227 @@ pub enum Symbol {
     Unknown,
 }
 
-trait NFA {
+impl Symbol {
+    pub fn to_char(&self) -> char {
+        match self {
+            Symbol::Operational => '.',
+            Symbol::Damaged     => '#',
+            Symbol::Unknown     => '?',
+        }
+    }
+
+    // The concrete springs an entry in a record could stand for
+    fn choices(&self) -> &'static [Symbol] {
+        match self {
+            Symbol::Operational => &[Symbol::Operational],
+            Symbol::Damaged     => &[Symbol::Damaged],
+            Symbol::Unknown     => &[Symbol::Operational, Symbol::Damaged],
+        }
+    }
+}
+
+pub fn render(symbols: &[Symbol]) -> String {
+    symbols.iter().map(Symbol::to_char).collect()
+}
+
+// The states of the NFA: an operational gap, then one state per damaged spring in each group,
+// each group followed by another gap
+fn states(groups: &[usize]) -> Vec<Symbol> {
+    let mut sequence = vec![Symbol::Operational];
+    for &size in groups {
+        for _ in 0..size {
+            sequence.push(Symbol::Damaged);
+        }
+        sequence.push(Symbol::Operational);
+    }
+
+    sequence
+}
+
+// Where the NFA can go from `state` on reading a spring that is definitely `symbol`
+fn step(sequence: &[Symbol], state: usize, symbol: &Symbol) -> impl Iterator<Item = usize> {
+    let next = (state + 1 < sequence.len() && sequence[state + 1] == *symbol).then_some(state + 1);
+    let stay = (*symbol == Symbol::Operational && sequence[state] == Symbol::Operational).then_some(state);
+
+    next.into_iter().chain(stay)
+}
+
+fn accepts(sequence: &[Symbol], state: usize) -> bool {
+    state + 2 >= sequence.len()
+}
+
+// completions[i][state] is the number of ways to finish the record from cell i in that state.
+// The first row is the total number of arrangements, and every other row is what lets the
+// enumeration and the sampler skip dead ends. A hundred unknown springs can already have more
+// arrangements than a u64 holds, so the counts are unbounded.
+fn completions(symbols: &[Symbol], sequence: &[Symbol]) -> Vec<Vec<BigUint>> {
+    let mut table = vec![vec![BigUint::zero(); sequence.len()]; symbols.len() + 1];
+    table[symbols.len()] = (0..sequence.len()).map(|state| BigUint::from(accepts(sequence, state) as u8)).collect();
+    for (idx, symbol) in symbols.iter().enumerate().rev() {
+        for state in 0..sequence.len() {
+            table[idx][state] = symbol
+                .choices()
+                .iter()
+                .flat_map(|choice| step(sequence, state, choice))
+                .map(|next| &table[idx + 1][next])
+                .sum();
+        }
+    }
+
+    table
+}
+
+// A uniform pick from 0..bound, drawing just enough random bits and trying again whenever they
+// land past the bound
+fn random_below(rng: &mut impl Rng, bound: &BigUint) -> BigUint {
+    let bits = bound.bits();
+    let mut bytes = vec![0; bits.div_ceil(8) as usize];
+    loop {
+        rng.fill(&mut bytes[..]);
+        if let Some(top) = bytes.last_mut() {
+            *top >>= bits.next_multiple_of(8) - bits;
+        }
+        let pick = BigUint::from_bytes_le(&bytes);
+        if &pick < bound {
+            return pick;
+        }
+    }
+}
+
+// Lazily walks every arrangement of a record, with operational springs tried before damaged ones.
+// Partial arrangements only go on the stack if they can still be completed, so every pop is
+// progress towards the next arrangement.
+pub struct Arrangements<'a> {
+    symbols: &'a [Symbol],
+    sequence: Vec<Symbol>,
+    table: Vec<Vec<BigUint>>,
+    stack: Vec<(usize, Vec<Symbol>)>,
+}
+
+impl Iterator for Arrangements<'_> {
+    type Item = Vec<Symbol>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((state, prefix)) = self.stack.pop() {
+            let idx = prefix.len();
+            if idx == self.symbols.len() {
+                return Some(prefix);
+            }
+            for choice in self.symbols[idx].choices().iter().rev() {
+                for next in step(&self.sequence, state, choice) {
+                    if !self.table[idx + 1][next].is_zero() {
+                        let mut arrangement = prefix.clone();
+                        arrangement.push(choice.clone());
+                        self.stack.push((next, arrangement));
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+const DEAD: usize = usize::MAX;
+
+// The NFA compiled for one group list. Each state only ever has one way forward on an operational
+// spring and one on a damaged spring, so it's a DFA in all but name, and the transitions fit in a
+// dense table indexed by state.
+pub struct Automaton {
+    table: Vec<[usize; 2]>,
+}
+
+// The two rows of state counts, kept between records so counting doesn't allocate
+pub struct Counts<T> {
+    current: Vec<T>,
+    next: Vec<T>,
+}
+
+impl<T> Default for Counts<T> {
+    fn default() -> Self {
+        Counts { current: Vec::new(), next: Vec::new() }
+    }
+}
+
+impl Automaton {
+    pub fn new(groups: &[usize]) -> Self {
+        let sequence = states(groups);
+        let table = (0..sequence.len())
+            .map(|state| {
+                [Symbol::Operational, Symbol::Damaged]
+                    .map(|symbol| step(&sequence, state, &symbol).next().unwrap_or(DEAD))
+            })
+            .collect();
+
+        Automaton { table }
+    }
+
+    // Number of arrangements of `symbols`, or None if it doesn't fit in T. The NFA can only move
+    // one state per spring, so after n springs only the first n + 1 states can be live.
+    pub fn count<T>(&self, symbols: &[Symbol], counts: &mut Counts<T>) -> Option<T>
+    where
+        T: Clone + Zero + One + CheckedAdd,
+    {
+        let states = self.table.len();
+        let Counts { current, next } = counts;
+        current.clear();
+        current.resize(states, T::zero());
+        next.clear();
+        next.resize(states, T::zero());
+        current[0] = T::one();
+
+        for (idx, symbol) in symbols.iter().enumerate() {
+            let live = (idx + 1).min(states);
+            for (count, &[on_operational, on_damaged]) in current[..live].iter().zip(&self.table) {
+                if count.is_zero() {
+                    continue;
+                }
+                let targets = match symbol {
+                    Symbol::Operational => [on_operational, DEAD],
+                    Symbol::Damaged     => [DEAD, on_damaged],
+                    Symbol::Unknown     => [on_operational, on_damaged],
+                };
+                for target in targets.into_iter().filter(|&target| target != DEAD) {
+                    next[target] = next[target].checked_add(count)?;
+                }
+            }
+            std::mem::swap(current, next);
+            next[..live].fill(T::zero());
+        }
+
+        current[states.saturating_sub(2)..]
+            .iter()
+            .try_fold(T::zero(), |total, count| total.checked_add(count))
+    }
+}
+
+// Every record unfolded by `factor` and counted in T. The buffers are set up once per rayon
+// worker rather than once per record.
+pub fn total<T>(input: &[Record], factor: usize) -> Option<T>
+where
+    T: Clone + Zero + One + CheckedAdd + Send,
+{
+    input
+        .par_iter()
+        .map_init(Counts::default, |counts, record| {
+            let (symbols, groups) = record.unfold(factor);
+            Automaton::new(&groups).count(&symbols, counts)
+        })
+        .reduce(|| Some(T::zero()), |a, b| a.zip(b).and_then(|(a, b)| a.checked_add(&b)))
+}
+
+pub trait NFA {
     fn permutations(&self) -> usize;
-    fn unfold(&self) -> Record;
+    fn unfold(&self, factor: usize) -> Record;
+    fn arrangements(&self) -> Arrangements<'_>;
+    fn sample(&self, rng: &mut impl Rng) -> Option<Vec<Symbol>>;
+    fn forced(&self) -> Option<Vec<Symbol>>;
 }
 
 impl NFA for Record {
     fn permutations(&self) -> usize {
         let symbols = &self.0;
-        let groups = &self.1;
-        let mut sequence = vec![Symbol::Operational];
-        for &size in groups {
-            for _ in 0..size {
-                sequence.push(Symbol::Damaged);
-            }
-            sequence.push(Symbol::Operational);
-        }
+        let sequence = states(&self.1);
     
         let mut powerset: HashMap<usize, usize> = HashMap::new();
         let mut subset: HashMap<usize, usize> = HashMap::new();
@@ -68,20 +275,238 @@ impl NFA for Record {
             + *powerset.get(&(sequence.len() - 2)).unwrap_or(&0)
     }
 
-    fn unfold(&self) -> Record {
+    // Joins `factor` copies of the springs with a `?` between each, and repeats the groups to match
+    fn unfold(&self, factor: usize) -> Record {
+        let mut symbols = Vec::with_capacity((self.0.len() + 1) * factor);
+        for copy in 0..factor {
+            if copy > 0 {
+                symbols.push(Symbol::Unknown);
+            }
+            symbols.extend_from_slice(&self.0);
+        }
+
         (
-            (0..4).fold(self.0.clone(), |mut acc, _| {
-                acc.push(Symbol::Unknown);
-                acc.extend_from_slice(&self.0);
-                acc
-            }),
+            symbols,
             repeat(&self.1)
-                .take(5)
+                .take(factor)
                 .flatten()
                 .cloned()
                 .collect::<Vec<usize>>(),
         )
     }
+
+    fn arrangements(&self) -> Arrangements<'_> {
+        let sequence = states(&self.1);
+        let table = completions(&self.0, &sequence);
+        let stack = if table[0][0].is_zero() { Vec::new() } else { vec![(0, Vec::new())] };
+
+        Arrangements { symbols: &self.0, sequence, table, stack }
+    }
+
+    // Picks each arrangement with equal probability. At every cell the next spring is chosen in
+    // proportion to how many arrangements it still leads to.
+    fn sample(&self, rng: &mut impl Rng) -> Option<Vec<Symbol>> {
+        let sequence = states(&self.1);
+        let table = completions(&self.0, &sequence);
+        if table[0][0].is_zero() {
+            return None;
+        }
+
+        let mut state = 0;
+        let mut arrangement = Vec::with_capacity(self.0.len());
+        for (idx, symbol) in self.0.iter().enumerate() {
+            let mut pick = random_below(rng, &table[idx][state]);
+            'choose: for choice in symbol.choices() {
+                for next in step(&sequence, state, choice) {
+                    if pick < table[idx + 1][next] {
+                        arrangement.push(choice.clone());
+                        state = next;
+                        break 'choose;
+                    }
+                    pick -= &table[idx + 1][next];
+                }
+            }
+        }
+
+        Some(arrangement)
+    }
+
+    // Cells that are damaged or operational in every arrangement, with `Unknown` left where it
+    // could go either way. Counting the arrangements that reach each cell from the left and
+    // finish from the right gives how many put a damaged spring there.
+    fn forced(&self) -> Option<Vec<Symbol>> {
+        let sequence = states(&self.1);
+        let table = completions(&self.0, &sequence);
+        let total = &table[0][0];
+        if total.is_zero() {
+            return None;
+        }
+
+        let mut reach = vec![BigUint::zero(); sequence.len()];
+        reach[0] = BigUint::one();
+        let mut forced = Vec::with_capacity(self.0.len());
+        for (idx, symbol) in self.0.iter().enumerate() {
+            let mut damaged = BigUint::zero();
+            let mut next_reach = vec![BigUint::zero(); sequence.len()];
+            for (state, count) in reach.iter().enumerate().filter(|(_, count)| !count.is_zero()) {
+                for choice in symbol.choices() {
+                    for next in step(&sequence, state, choice) {
+                        next_reach[next] += count;
+                        if *choice == Symbol::Damaged {
+                            damaged += count * &table[idx + 1][next];
+                        }
+                    }
+                }
+            }
+            forced.push(if damaged.is_zero() {
+                Symbol::Operational
+            } else if &damaged == total {
+                Symbol::Damaged
+            } else {
+                Symbol::Unknown
+            });
+            reach = next_reach;
+        }
+
+        Some(forced)
+    }
+}
+
+pub type Grid = Vec<Vec<Symbol>>;
+
+#[derive(Debug, PartialEq)]
+pub enum NonogramError {
+    BadClue(String),
+    MissingColumns,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Solution {
+    None,
+    Unique(Grid),
+    Multiple(Grid, Grid),
+}
+
+// A picross puzzle. Every row and column is a day 12 record with nothing known yet, so the line
+// automaton can work out what each clue forces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Nonogram {
+    pub rows: Vec<Vec<usize>>,
+    pub cols: Vec<Vec<usize>>,
+}
+
+impl Nonogram {
+    // Row clues, a blank line, then column clues. Each clue is written like a day 12 group list,
+    // with a 0 for an empty line.
+    pub fn parse(input: &str) -> Result<Self, NonogramError> {
+        let mut blocks = [Vec::new(), Vec::new()];
+        let mut block = 0;
+        for line in input.lines().map(str::trim) {
+            if line.is_empty() {
+                block += 1;
+                continue;
+            }
+            let clue = line
+                .split(',')
+                .map(|num| num.trim().parse::<usize>())
+                .collect::<Result<Vec<usize>, _>>()
+                .map_err(|_| NonogramError::BadClue(line.to_string()))?;
+            blocks
+                .get_mut(block)
+                .ok_or(NonogramError::BadClue(line.to_string()))?
+                .push(clue.into_iter().filter(|&size| size > 0).collect());
+        }
+        let [rows, cols] = blocks;
+        if cols.is_empty() {
+            return Err(NonogramError::MissingColumns);
+        }
+
+        Ok(Nonogram { rows, cols })
+    }
+
+    pub fn blank(&self) -> Grid {
+        vec![vec![Symbol::Unknown; self.cols.len()]; self.rows.len()]
+    }
+
+    // Applies the line automaton to every row and column until nothing changes. Returns false if
+    // some line can't be satisfied, in which case the grid is left part way through.
+    pub fn propagate(&self, grid: &mut Grid) -> bool {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (row, clue) in self.rows.iter().enumerate() {
+                let Some(forced) = (grid[row].clone(), clue.clone()).forced() else {
+                    return false;
+                };
+                for (col, symbol) in forced.into_iter().enumerate() {
+                    if symbol != Symbol::Unknown && grid[row][col] == Symbol::Unknown {
+                        grid[row][col] = symbol;
+                        changed = true;
+                    }
+                }
+            }
+            for (col, clue) in self.cols.iter().enumerate() {
+                let line = grid.iter().map(|cells| cells[col].clone()).collect();
+                let Some(forced) = (line, clue.clone()).forced() else {
+                    return false;
+                };
+                for (row, symbol) in forced.into_iter().enumerate() {
+                    if symbol != Symbol::Unknown && grid[row][col] == Symbol::Unknown {
+                        grid[row][col] = symbol;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        true
+    }
+
+    // Line solving first, then guessing the first open cell whenever that runs dry. Stops once
+    // `limit` solutions have turned up.
+    fn search(&self, mut grid: Grid, limit: usize, found: &mut Vec<Grid>) {
+        if found.len() >= limit || !self.propagate(&mut grid) {
+            return;
+        }
+        let open = grid
+            .iter()
+            .enumerate()
+            .find_map(|(row, cells)| cells.iter().position(|cell| *cell == Symbol::Unknown).map(|col| (row, col)));
+        match open {
+            None => found.push(grid),
+            Some((row, col)) => {
+                for guess in [Symbol::Damaged, Symbol::Operational] {
+                    let mut attempt = grid.clone();
+                    attempt[row][col] = guess;
+                    self.search(attempt, limit, found);
+                }
+            }
+        }
+    }
+
+    pub fn solve_all(&self) -> Vec<Grid> {
+        let mut found = Vec::new();
+        self.search(self.blank(), usize::MAX, &mut found);
+
+        found
+    }
+
+    // Only needs to find a second solution to show the first isn't unique
+    pub fn solve(&self) -> Solution {
+        let mut found = Vec::new();
+        self.search(self.blank(), 2, &mut found);
+        let mut found = found.into_iter();
+
+        match (found.next(), found.next()) {
+            (None, _)                   => Solution::None,
+            (Some(grid), None)          => Solution::Unique(grid),
+            (Some(first), Some(second)) => Solution::Multiple(first, second),
+        }
+    }
+}
+
+pub fn render_grid(grid: &Grid) -> String {
+    grid.iter().map(|row| render(row)).collect::<Vec<String>>().join("\n")
 }
 
 #[aoc_generator(day12)]
@@ -112,24 +537,29 @@ pub fn input_generator(input: &str) -> Vec<Record> {
 }
 
 #[aoc(day12, part1)]
-pub fn solve_part1(input: &Vec<Record>) -> usize {
-    input
-        .par_iter()
-        .map(|line| line.permutations())
-        .sum()
+pub fn solve_part1(input: &[Record]) -> u64 {
+    total(input, 1).expect("Too many arrangements.")
 }
 
 #[aoc(day12, part2)]
-pub fn solve_part2(input: &Vec<Record>) -> usize {
+pub fn solve_part2(input: &[Record]) -> u64 {
+    total(input, 5).expect("Too many arrangements.")
+}
+
+// The original powerset construction, with a fresh map of states for every spring
+#[aoc(day12, part2, nfa)]
+pub fn solve_part2_nfa(input: &[Record]) -> usize {
     input
         .par_iter()
-        .map(|line| line.unfold().permutations())
+        .map(|line| line.unfold(5).permutations())
         .sum()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use num::BigUint;
+    use rand::{rngs::StdRng, SeedableRng};
 
     const TEST: &str = "???.### 1,1,3
                         .??..??...?##. 1,1,3
@@ -146,5 +576,136 @@ mod tests {
     #[test]
     fn part2_test() {
         assert_eq!(solve_part2(&input_generator(TEST)), 525_152);
+        assert_eq!(solve_part2_nfa(&input_generator(TEST)), 525_152);
+    }
+
+    #[test]
+    fn automaton() {
+        let records = input_generator(TEST);
+        let mut counts = Counts::default();
+        for record in &records {
+            for factor in 1..4 {
+                let (symbols, groups) = record.unfold(factor);
+                let expected = (symbols.clone(), groups.clone()).permutations() as u64;
+                assert_eq!(Automaton::new(&groups).count(&symbols, &mut counts), Some(expected));
+            }
+        }
+
+        assert_eq!(Automaton::new(&[]).count::<u64>(&[], &mut counts), Some(1));
+
+        // The last record grows by roughly 15x per fold, so a u64 gives out well before 20
+        assert_eq!(total::<u64>(&records, 20), None);
+        let big = total::<BigUint>(&records, 20).unwrap();
+        assert_eq!(total::<u128>(&records, 20).map(BigUint::from), Some(big.clone()));
+        assert!(big > BigUint::from(u64::MAX));
+        assert_eq!(total::<u128>(&records, 5), Some(525_152));
+    }
+
+    // Group sizes of a fully known arrangement
+    fn groups(arrangement: &[Symbol]) -> Vec<usize> {
+        render(arrangement)
+            .split('.')
+            .filter(|run| !run.is_empty())
+            .map(|run| run.len())
+            .collect()
+    }
+
+    #[test]
+    fn arrangements() {
+        for record in input_generator(TEST) {
+            let all: Vec<Vec<Symbol>> = record.arrangements().collect();
+            assert_eq!(all.len(), record.permutations());
+            for arrangement in &all {
+                assert_eq!(groups(arrangement), record.1);
+                assert!(record.0.iter().zip(arrangement).all(|(symbol, spring)| {
+                    *symbol == Symbol::Unknown || symbol == spring
+                }));
+            }
+            // Operational comes first, and '.' sorts after '#'
+            assert!(all.windows(2).all(|pair| render(&pair[0]) > render(&pair[1])));
+        }
+        let first = &input_generator(TEST)[0];
+        assert_eq!(first.arrangements().map(|a| render(&a)).collect::<Vec<_>>(), ["#.#.###"]);
+        assert_eq!(input_generator("??? 4")[0].arrangements().count(), 0);
+    }
+
+    #[test]
+    fn sampling() {
+        let record = &input_generator(TEST)[5];
+        let mut rng = StdRng::seed_from_u64(12);
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for _ in 0..10_000 {
+            let arrangement = record.sample(&mut rng).unwrap();
+            assert_eq!(groups(&arrangement), record.1);
+            *seen.entry(render(&arrangement)).or_default() += 1;
+        }
+        assert_eq!(seen.len(), 10);
+        assert!(seen.values().all(|&count| (700..1300).contains(&count)));
+        assert_eq!(input_generator("??? 4")[0].sample(&mut rng), None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn forced() {
+        let records = input_generator(TEST);
+        assert_eq!(render(&records[0].forced().unwrap()), "#.#.###");
+        assert_eq!(render(&records[1].forced().unwrap()), ".??..??...###.");
+        assert_eq!(render(&records[5].forced().unwrap()), ".###.???????");
+        assert_eq!(input_generator("??? 4")[0].forced(), None);
+
+        // More arrangements than a u64 holds, and no cell is settled
+        let wide = (vec![Symbol::Unknown; 100], vec![1; 30]);
+        assert_eq!(total::<u64>(std::slice::from_ref(&wide), 1), None);
+        assert_eq!(total::<u128>(std::slice::from_ref(&wide), 1), Some(95_846_086_442_150_951_368));
+        assert_eq!(wide.forced(), Some(vec![Symbol::Unknown; 100]));
+        let mut rng = StdRng::seed_from_u64(12);
+        assert_eq!(groups(&wide.sample(&mut rng).unwrap()), wide.1);
+        assert_eq!(groups(&wide.arrangements().next().unwrap()), wide.1);
+    }
+
+    const HEART: &str = "1,1
+                         5
+                         5
+                         3
+                         1
+
+                         2
+                         4
+                         4
+                         4
+                         2";
+
+    #[test]
+    fn nonogram() {
+        let puzzle = Nonogram::parse(HEART).unwrap();
+        let Solution::Unique(grid) = puzzle.solve() else {
+            panic!("expected a unique solution");
+        };
+        assert_eq!(render_grid(&grid), ".#.#.\n#####\n#####\n.###.\n..#..");
+
+        // Line solving alone gets all the way there
+        let mut grid = puzzle.blank();
+        assert!(puzzle.propagate(&mut grid));
+        assert_eq!(render_grid(&grid), ".#.#.\n#####\n#####\n.###.\n..#..");
+
+        // Two diagonals, so it needs a guess
+        let ambiguous = Nonogram::parse("1\n1\n\n1\n1").unwrap();
+        assert!(matches!(ambiguous.solve(), Solution::Multiple(..)));
+        assert_eq!(ambiguous.solve_all().len(), 2);
+
+        let impossible = Nonogram::parse("2\n0\n\n1\n0").unwrap();
+        assert_eq!(impossible.solve(), Solution::None);
+        assert!(impossible.solve_all().is_empty());
+
+        // A single row whose clue alone has more arrangements than a u64 holds, settled by the columns
+        let clue = vec!["1"; 30].join(",");
+        let cols: Vec<&str> = (0..100).map(|col| if col % 3 == 0 && col < 90 { "1" } else { "0" }).collect();
+        let wide = Nonogram::parse(&format!("{}\n\n{}", clue, cols.join("\n"))).unwrap();
+        let Solution::Unique(grid) = wide.solve() else {
+            panic!("expected a unique solution");
+        };
+        assert_eq!(render_grid(&grid), "#..".repeat(30) + &".".repeat(10));
+
+        assert_eq!(Nonogram::parse("1\nx\n\n1"), Err(NonogramError::BadClue("x".to_string())));
+        assert_eq!(Nonogram::parse("1\n1"), Err(NonogramError::MissingColumns));
+    }
+}
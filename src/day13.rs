@@ -1,3 +1,63 @@
+// Which way a mirror line runs. A horizontal line sits between two rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+// A mirror line with `line` rows or columns before it, and how many cells disagree with it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reflection {
+    pub axis: Axis,
+    pub line: usize,
+    pub smudges: u32,
+}
+
+impl Reflection {
+    pub fn score(&self) -> usize {
+        match self.axis {
+            Axis::Horizontal => 100 * self.line,
+            Axis::Vertical   => self.line,
+        }
+    }
+}
+
+// Every kind of symmetry we look for, with the plain mirror line as just one of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Symmetry {
+    // Mirror between two lines, with `line` of them before it
+    Between { axis: Axis, line: usize },
+    // Mirror running through the middle of a line
+    Through { axis: Axis, line: usize },
+    // Top left to bottom right, square blocks only
+    Diagonal,
+    // Top right to bottom left, square blocks only
+    AntiDiagonal,
+    // Looks the same turned upside down
+    HalfTurn,
+    // Every line matches the one `period` further along
+    Period { axis: Axis, period: usize },
+}
+
+// A symmetry and the number of mismatched cells. For the mirrors and turns that's the number of
+// cells that would have to flip to make it exact; for a period it's the number of cells that differ
+// from the one a period along.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fit {
+    pub symmetry: Symmetry,
+    pub mismatches: u32,
+}
+
+// The lowest `width` bits in the opposite order
+fn reverse(bits: usize, width: usize) -> usize {
+    bits.reverse_bits().checked_shr(usize::BITS - width as u32).unwrap_or(0)
+}
+
+// Pairs of lines that face each other across a mirror after `line` of them
+fn mirrored(len: usize, line: usize) -> impl Iterator<Item = (usize, usize)> {
+    (0..line.min(len - line)).map(move |offset| (line - offset - 1, line + offset))
+}
+
 #[derive(Debug)]
 pub struct Block {
     rows: Vec<usize>,
@@ -5,23 +65,121 @@ pub struct Block {
 }
 
 impl Block {
-    fn block_score(&self, smudged: bool) -> usize {
-        match smudged {
-            false => {
-                match self.find_mirror((None,None)).unwrap() {
-                    (Some(score), None) => 100 * score,
-                    (None, Some(score)) => score,
-                    _ => unreachable!(),
-                }
+    fn block_score(&self, smudges: u32) -> usize {
+        self.with_smudges(smudges).next().unwrap().score()
+    }
+
+    fn lines(&self, axis: Axis) -> &[usize] {
+        match axis {
+            Axis::Horizontal => &self.rows,
+            Axis::Vertical   => &self.cols,
+        }
+    }
+
+    // Every possible mirror line. XORing a row with its mirror image leaves a bit set for each
+    // cell that doesn't match, so the popcount over all the pairs is the number of smudges.
+    pub fn reflections(&self) -> Vec<Reflection> {
+        [Axis::Horizontal, Axis::Vertical]
+            .into_iter()
+            .flat_map(|axis| {
+                let lines = self.lines(axis);
+                (1..lines.len()).map(move |line| Reflection {
+                    axis,
+                    line,
+                    smudges: mirrored(lines.len(), line)
+                        .map(|(a, b)| (lines[a] ^ lines[b]).count_ones())
+                        .sum(),
+                })
+            })
+            .collect()
+    }
+
+    pub fn with_smudges(&self, smudges: u32) -> impl Iterator<Item = Reflection> {
+        self.reflections().into_iter().filter(move |reflection| reflection.smudges == smudges)
+    }
+
+    pub fn within_smudges(&self, smudges: u32) -> impl Iterator<Item = Reflection> {
+        self.reflections().into_iter().filter(move |reflection| reflection.smudges <= smudges)
+    }
+
+    // How well the block fits a symmetry, or None if it can't apply. Transposing a square block turns
+    // columns into rows, so both diagonals and the half turn come down to comparing one row mask with
+    // another, possibly reversed. Each mismatched pair of cells shows up twice over the whole block.
+    pub fn fit(&self, symmetry: Symmetry) -> Option<u32> {
+        let (height, width) = (self.rows.len(), self.cols.len());
+        let pairs = |lines: &[usize], pair: &dyn Fn(usize) -> usize| -> u32 {
+            lines.iter().enumerate().map(|(idx, &line)| (line ^ pair(idx)).count_ones()).sum::<u32>() / 2
+        };
+
+        match symmetry {
+            Symmetry::Between { axis, line } => {
+                let lines = self.lines(axis);
+                (0 < line && line < lines.len()).then(|| {
+                    mirrored(lines.len(), line).map(|(a, b)| (lines[a] ^ lines[b]).count_ones()).sum()
+                })
             },
-            true  => {
-                match self.find_clean().unwrap() {
-                    (Some(score), None) => 100 * score,
-                    (None, Some(score)) => score,
-                    _ => unreachable!(),
-                }
+            Symmetry::Through { axis, line } => {
+                let lines = self.lines(axis);
+                (0 < line && line + 1 < lines.len()).then(|| {
+                    (1..=line.min(lines.len() - line - 1))
+                        .map(|offset| (lines[line - offset] ^ lines[line + offset]).count_ones())
+                        .sum()
+                })
+            },
+            Symmetry::Diagonal => {
+                (height == width).then(|| pairs(&self.rows, &|row| self.cols[row]))
+            },
+            Symmetry::AntiDiagonal => {
+                (height == width).then(|| pairs(&self.rows, &|row| reverse(self.cols[width - row - 1], height)))
+            },
+            Symmetry::HalfTurn => {
+                Some(pairs(&self.rows, &|row| reverse(self.rows[height - row - 1], width)))
             },
+            Symmetry::Period { axis, period } => {
+                let lines = self.lines(axis);
+                (0 < period && period < lines.len()).then(|| {
+                    lines.iter().zip(&lines[period..]).map(|(a, b)| (a ^ b).count_ones()).sum()
+                })
+            },
+        }
+    }
+
+    // Every symmetry that applies to this block, however badly it fits
+    pub fn symmetries(&self) -> Vec<Fit> {
+        let mut candidates = vec![Symmetry::Diagonal, Symmetry::AntiDiagonal, Symmetry::HalfTurn];
+        for axis in [Axis::Horizontal, Axis::Vertical] {
+            for line in 0..self.lines(axis).len() {
+                candidates.push(Symmetry::Between { axis, line });
+                candidates.push(Symmetry::Through { axis, line });
+                candidates.push(Symmetry::Period { axis, period: line });
+            }
         }
+
+        candidates
+            .into_iter()
+            .filter_map(|symmetry| self.fit(symmetry).map(|mismatches| Fit { symmetry, mismatches }))
+            .collect()
+    }
+
+    // The (row, col) cells to flip to make a reflection perfect, taken from the side before the line
+    pub fn flips(&self, reflection: &Reflection) -> Vec<(usize, usize)> {
+        let lines = self.lines(reflection.axis);
+        let across = match reflection.axis {
+            Axis::Horizontal => self.cols.len(),
+            Axis::Vertical   => self.rows.len(),
+        };
+
+        mirrored(lines.len(), reflection.line)
+            .flat_map(|(a, b)| {
+                let diff = lines[a] ^ lines[b];
+                (0..across)
+                    .filter(move |&pos| diff & (1 << (across - pos - 1)) != 0)
+                    .map(move |pos| match reflection.axis {
+                        Axis::Horizontal => (a, pos),
+                        Axis::Vertical   => (pos, a),
+                    })
+            })
+            .collect()
     }
 
     fn clone_with_mut(&self) -> Block {
@@ -121,18 +279,33 @@ pub fn input_generator(input: &str) -> Vec<Block> {
 
 
 #[aoc(day13, part1)]
-pub fn solve_part1(input: &Vec<Block>) -> usize {
+pub fn solve_part1(input: &[Block]) -> usize {
     input
         .iter()
-        .map(|block| block.block_score(false))
+        .map(|block| block.block_score(0))
         .sum()
 }
 
 #[aoc(day13, part2)]
-pub fn solve_part2(input: &Vec<Block>) -> usize {
+pub fn solve_part2(input: &[Block]) -> usize {
+    input
+        .iter()
+        .map(|block| block.block_score(1))
+        .sum()
+}
+
+// The original search, flipping one cell at a time and looking for a new mirror
+#[aoc(day13, part2, flip)]
+pub fn solve_part2_flip(input: &[Block]) -> usize {
     input
         .iter()
-        .map(|block| block.block_score(true))
+        .map(|block| {
+            match block.find_clean().unwrap() {
+                (Some(score), None) => 100 * score,
+                (None, Some(score)) => score,
+                _ => unreachable!(),
+            }
+        })
         .sum()
 }
 
@@ -164,5 +337,69 @@ mod tests {
     #[test]
     fn part2_test() {
         assert_eq!(solve_part2(&input_generator(TEST)), 400);
+        assert_eq!(solve_part2_flip(&input_generator(TEST)), 400);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn smudges() {
+        let input = input_generator(TEST);
+        let clean = Reflection { axis: Axis::Vertical, line: 5, smudges: 0 };
+        let smudged = Reflection { axis: Axis::Horizontal, line: 3, smudges: 1 };
+        assert_eq!(input[0].with_smudges(0).collect::<Vec<_>>(), std::slice::from_ref(&clean));
+        assert_eq!(input[0].with_smudges(1).collect::<Vec<_>>(), std::slice::from_ref(&smudged));
+        assert_eq!(input[0].within_smudges(1).collect::<Vec<_>>(), [smudged.clone(), clean]);
+        assert_eq!(input[0].flips(&smudged), [(0, 0)]);
+        assert_eq!(input[1].flips(&input[1].with_smudges(1).next().unwrap()), [(0, 4)]);
+        assert_eq!(input[1].reflections().len(), 6 + 8);
+
+        for block in &input {
+            for reflection in block.reflections() {
+                let mut fixed = block.clone_with_mut();
+                for (row, col) in block.flips(&reflection) {
+                    fixed.rows[row] ^= 1 << (block.cols.len() - col - 1);
+                    fixed.cols[col] ^= 1 << (block.rows.len() - row - 1);
+                }
+                assert_eq!(block.flips(&reflection).len(), reflection.smudges as usize);
+                assert!(fixed.with_smudges(0).any(|fixed| fixed.axis == reflection.axis && fixed.line == reflection.line));
+            }
+        }
+    }
+
+    #[test]
+    fn symmetries() {
+        let input = input_generator("##.
+                                     #..
+                                     ...");
+        let fit = |symmetry| input[0].fit(symmetry);
+        assert_eq!(fit(Symmetry::Diagonal), Some(0));
+        assert_eq!(fit(Symmetry::AntiDiagonal), Some(3));
+        assert_eq!(fit(Symmetry::HalfTurn), Some(3));
+        assert_eq!(fit(Symmetry::Through { axis: Axis::Horizontal, line: 1 }), Some(2));
+        assert_eq!(fit(Symmetry::Through { axis: Axis::Vertical, line: 1 }), Some(2));
+        assert_eq!(fit(Symmetry::Through { axis: Axis::Vertical, line: 2 }), None);
+        assert_eq!(fit(Symmetry::Period { axis: Axis::Horizontal, period: 1 }), Some(2));
+
+        let input = input_generator("#.#.
+                                     .#.#");
+        let fit = |symmetry| input[0].fit(symmetry);
+        assert_eq!(fit(Symmetry::Period { axis: Axis::Vertical, period: 2 }), Some(0));
+        assert_eq!(fit(Symmetry::Period { axis: Axis::Vertical, period: 1 }), Some(6));
+        assert_eq!(fit(Symmetry::HalfTurn), Some(0));
+        assert_eq!(fit(Symmetry::Diagonal), None);
+
+        // The plain mirrors agree with the smudge search
+        for block in input_generator(TEST) {
+            for reflection in block.reflections() {
+                let symmetry = Symmetry::Between { axis: reflection.axis, line: reflection.line };
+                assert_eq!(block.fit(symmetry), Some(reflection.smudges));
+            }
+            let perfect: Vec<Symmetry> = block
+                .symmetries()
+                .into_iter()
+                .filter(|fit| fit.mismatches == 0)
+                .map(|fit| fit.symmetry)
+                .collect();
+            assert!(perfect.iter().any(|symmetry| matches!(symmetry, Symmetry::Between { .. })));
+        }
+    }
+}
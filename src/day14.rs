@@ -231,6 +231,277 @@ impl fmt::Display for Platform {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    North,
+    West,
+    South,
+    East,
+}
+
+impl Direction {
+    // Clockwise quarter turns that bring this edge round to the top
+    fn turns(self) -> usize {
+        match self {
+            Direction::North => 0,
+            Direction::West  => 1,
+            Direction::South => 2,
+            Direction::East  => 3,
+        }
+    }
+}
+
+// Turns a board a quarter turn clockwise. Boards are stored as one mask per column with bit r
+// set for row r, and after the turn the old rows are the new columns. Only the set bits are
+// visited, which for rocks is a fraction of the board.
+fn rotate(cols: &[u128], height: usize) -> Vec<u128> {
+    let mut rotated = vec![0; height];
+    for (col, &old) in cols.iter().enumerate() {
+        let mut bits = old;
+        while bits != 0 {
+            let row = bits.trailing_zeros() as usize;
+            rotated[height - row - 1] |= 1 << col;
+            bits &= bits - 1;
+        }
+    }
+
+    rotated
+}
+
+// The runs of open cells between cubes in each column, as the first row and a mask of the run
+fn runs(cubes: &[u128], height: usize) -> Vec<Vec<(u32, u128)>> {
+    cubes
+        .iter()
+        .map(|&col| {
+            let mut open = !col & ((1 << height) - 1);
+            let mut runs = Vec::new();
+            while open != 0 {
+                let start = open.trailing_zeros();
+                let len = (!(open >> start)).trailing_zeros();
+                let run = ((1 << len) - 1) << start;
+                runs.push((start, run));
+                open &= !run;
+            }
+            runs
+        })
+        .collect()
+}
+
+// The platform as bitboards. Only tilting north is done directly: the rocks in each run between
+// cubes get counted and packed to the top of it. Any other direction turns the board so that
+// edge is at the top first. The cubes never move, so their masks and runs are worked out once for
+// each of the four ways up. Platforms can be at most 127 tiles across either way.
+#[derive(Debug, Clone)]
+pub struct Board {
+    width: usize,
+    height: usize,
+    // Clockwise quarter turns from the way the platform was read in
+    turns: usize,
+    rocks: Vec<u128>,
+    cubes: [Vec<u128>; 4],
+    runs: [Vec<Vec<(u32, u128)>>; 4],
+}
+
+impl From<&Platform> for Board {
+    fn from(platform: &Platform) -> Self {
+        let (width, height) = (platform.width, platform.height);
+        assert!(width < 128 && height < 128, "Platform too large for a bitboard.");
+        let mask = |tile| {
+            (0..width)
+                .map(|col| {
+                    (0..height)
+                        .filter(|&row| platform.map.get(&(col, row)) == Some(&tile))
+                        .fold(0, |mask, row| mask | 1 << row)
+                })
+                .collect::<Vec<u128>>()
+        };
+
+        let mut cubes = [mask(Tile::Cube), Vec::new(), Vec::new(), Vec::new()];
+        for turns in 1..4 {
+            let size = if turns % 2 == 1 { height } else { width };
+            cubes[turns] = rotate(&cubes[turns - 1], size);
+        }
+        let runs = [0, 1, 2, 3].map(|turns| runs(&cubes[turns], if turns % 2 == 0 { height } else { width }));
+
+        Board { width, height, turns: 0, rocks: mask(Tile::Round), cubes, runs }
+    }
+}
+
+impl Board {
+    // Height of the board the way it's currently turned
+    fn current_height(&self) -> usize {
+        if self.turns % 2 == 0 { self.height } else { self.width }
+    }
+
+    fn turn_to(&mut self, turns: usize) {
+        while self.turns != turns {
+            self.rocks = rotate(&self.rocks, self.current_height());
+            self.turns = (self.turns + 1) % 4;
+        }
+    }
+
+    fn tilt_north(&mut self) {
+        for (col, runs) in self.rocks.iter_mut().zip(&self.runs[self.turns]) {
+            *col = runs
+                .iter()
+                .fold(0, |packed, &(start, run)| packed | ((1 << (*col & run).count_ones()) - 1) << start);
+        }
+    }
+
+    pub fn tilt(&mut self, direction: Direction) {
+        self.turn_to(direction.turns());
+        self.tilt_north();
+    }
+
+    // Runs the program once, and turns back the way the platform was read in so states can be compared
+    pub fn apply(&mut self, program: &Program) {
+        for &direction in &program.0 {
+            self.tilt(direction);
+        }
+        self.turn_to(0);
+    }
+
+    // Every state the program passes through, up to the first one that comes round again
+    pub fn orbit(&self, program: &Program) -> Orbit {
+        let mut board = self.clone();
+        board.turn_to(0);
+        let mut seen = HashMap::new();
+        let mut states = Vec::new();
+        let start = loop {
+            if let Some(&start) = seen.get(&board.rocks) {
+                break start;
+            }
+            seen.insert(board.rocks.clone(), states.len());
+            states.push(board.rocks.clone());
+            board.apply(program);
+        };
+
+        Orbit { template: board, states, start }
+    }
+
+    // Runs the program `n` times, skipping ahead once a state comes round again
+    pub fn run(&self, program: &Program, n: usize) -> Board {
+        self.orbit(program).state(n)
+    }
+
+    // Each round rock adds its distance from the far side of the platform to the load on an edge
+    pub fn load(&self, edge: Direction) -> usize {
+        let mut board = self.clone();
+        board.turn_to(edge.turns());
+        let height = board.current_height();
+
+        board
+            .rocks
+            .iter()
+            .map(|&col| (0..height).filter(|&row| col & 1 << row != 0).map(|row| height - row).sum::<usize>())
+            .sum()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ProgramError {
+    Empty,
+    UnknownStep(String),
+}
+
+// A sequence of tilts run as one step, written as edge letters like "N W S E"
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program(pub Vec<Direction>);
+
+impl Program {
+    pub fn parse(input: &str) -> Result<Self, ProgramError> {
+        let steps = input
+            .split_whitespace()
+            .map(|step| match step {
+                "N" => Ok(Direction::North),
+                "W" => Ok(Direction::West),
+                "S" => Ok(Direction::South),
+                "E" => Ok(Direction::East),
+                _   => Err(ProgramError::UnknownStep(step.to_string())),
+            })
+            .collect::<Result<Vec<Direction>, ProgramError>>()?;
+        if steps.is_empty() {
+            return Err(ProgramError::Empty);
+        }
+
+        Ok(Program(steps))
+    }
+
+    // The spin cycle from part 2
+    pub fn spin() -> Self {
+        Program(vec![Direction::North, Direction::West, Direction::South, Direction::East])
+    }
+}
+
+// The states a program visits from some starting board. States before `start` are only seen
+// once, and the rest repeat forever.
+#[derive(Debug, Clone)]
+pub struct Orbit {
+    // Supplies the cubes for each state
+    template: Board,
+    states: Vec<Vec<u128>>,
+    pub start: usize,
+}
+
+impl Orbit {
+    pub fn period(&self) -> usize {
+        self.states.len() - self.start
+    }
+
+    fn board(&self, rocks: &[u128]) -> Board {
+        Board { rocks: rocks.to_vec(), ..self.template.clone() }
+    }
+
+    // The board after any number of steps
+    pub fn state(&self, step: usize) -> Board {
+        if step < self.states.len() {
+            self.board(&self.states[step])
+        } else {
+            self.board(&self.states[self.start + (step - self.start) % self.period()])
+        }
+    }
+
+    pub fn pre_period(&self) -> impl Iterator<Item = Board> + '_ {
+        self.states[..self.start].iter().map(|rocks| self.board(rocks))
+    }
+
+    pub fn cycle(&self) -> impl Iterator<Item = Board> + '_ {
+        self.states[self.start..].iter().map(|rocks| self.board(rocks))
+    }
+
+    // One line per state with the load on each edge, for loading into something else
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("step,phase,north,west,south,east\n");
+        for (step, board) in self.pre_period().chain(self.cycle()).enumerate() {
+            let phase = if step < self.start { "pre" } else { "cycle" };
+            let [north, west, south, east] =
+                [Direction::North, Direction::West, Direction::South, Direction::East].map(|edge| board.load(edge));
+            csv.push_str(&format!("{step},{phase},{north},{west},{south},{east}\n"));
+        }
+
+        csv
+    }
+}
+
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut board = self.clone();
+        board.turn_to(0);
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let symbol = match (board.cubes[0][col] >> row & 1, board.rocks[col] >> row & 1) {
+                    (1, _) => '#',
+                    (_, 1) => 'O',
+                    _      => '.',
+                };
+                write!(f, "{}", symbol)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
 #[aoc_generator(day14)]
 pub fn input_generator(input: &str) -> Platform {
     Platform { 
@@ -256,11 +527,24 @@ pub fn input_generator(input: &str) -> Platform {
 
 #[aoc(day14, part1)]
 pub fn solve_part1(input: &Platform) -> usize {
+    let mut board = Board::from(input);
+    board.tilt(Direction::North);
+
+    board.load(Direction::North)
+}
+
+#[aoc(day14, part1, map)]
+pub fn solve_part1_map(input: &Platform) -> usize {
     input.clone().tip_north().calculate_load()
 }
 
 #[aoc(day14, part2)]
 pub fn solve_part2(input: &Platform) -> usize {
+    Board::from(input).run(&Program::spin(), 1_000_000_000).load(Direction::North)
+}
+
+#[aoc(day14, part2, brent)]
+pub fn solve_part2_brent(input: &Platform) -> usize {
     // println!("{}\n{}", input, input.clone().cycles(3));
     // input.clone().floyd_cycles(1_000_000_000).calculate_load()
     input.clone().brent_cycles(1_000_000_000).calculate_load()
@@ -269,6 +553,8 @@ pub fn solve_part2(input: &Platform) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::time::Instant;
 
     const TEST: &str = "O....#....
                         O.OO#....#
@@ -284,10 +570,111 @@ mod tests {
     #[test]
     fn part1_test() {
         assert_eq!(solve_part1(&input_generator(TEST)), 136);
+        assert_eq!(solve_part1_map(&input_generator(TEST)), 136);
     }
 
     #[test]
     fn part2_test() {
         assert_eq!(solve_part2(&input_generator(TEST)), 64);
+        assert_eq!(solve_part2_brent(&input_generator(TEST)), 64);
+    }
+
+    fn random_platform(rng: &mut impl Rng, width: usize, height: usize) -> String {
+        (0..height)
+            .map(|_| {
+                (0..width)
+                    .map(|_| match rng.gen_range(0..10) {
+                        0 | 1 => '#',
+                        2..=4 => 'O',
+                        _     => '.',
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn bitboard() {
+        let mut platforms = vec![input_generator(TEST)];
+        let mut rng = StdRng::seed_from_u64(14);
+        platforms.extend((0..20).map(|_| input_generator(&random_platform(&mut rng, 13, 7))));
+        for platform in platforms {
+            let tipped = [
+                (Direction::North, platform.clone().tip_north()),
+                (Direction::West, platform.clone().tip_west()),
+                (Direction::South, platform.clone().tip_south()),
+                (Direction::East, platform.clone().tip_east()),
+            ];
+            for (direction, expected) in tipped {
+                let mut board = Board::from(&platform);
+                board.tilt(direction);
+                assert_eq!(board.to_string(), expected.to_string());
+                assert_eq!(board.load(Direction::North), expected.calculate_load());
+            }
+            let board = Board::from(&platform);
+            for n in 0..4 {
+                assert_eq!(board.run(&Program::spin(), n).to_string(), platform.clone().cycles(n).to_string());
+            }
+            let spun = board.run(&Program::spin(), 1_000);
+            assert_eq!(spun.to_string(), platform.clone().brent_cycles(1_000).to_string());
+        }
     }
-}
\ No newline at end of file
+
+    // Load on an edge worked out from the rendered board
+    fn rendered_load(board: &Board, edge: Direction) -> usize {
+        let rendered = board.to_string();
+        let rows: Vec<&str> = rendered.lines().collect();
+        let (height, width) = (rows.len(), rows[0].len());
+        rows.iter()
+            .enumerate()
+            .flat_map(|(row, line)| line.match_indices('O').map(move |(col, _)| (row, col)))
+            .map(|(row, col)| match edge {
+                Direction::North => height - row,
+                Direction::South => row + 1,
+                Direction::West  => width - col,
+                Direction::East  => col + 1,
+            })
+            .sum()
+    }
+
+    #[test]
+    fn programs() {
+        assert_eq!(Program::parse("N W S E"), Ok(Program::spin()));
+        assert_eq!(Program::parse("N X"), Err(ProgramError::UnknownStep("X".to_string())));
+        assert_eq!(Program::parse("  "), Err(ProgramError::Empty));
+
+        let platform = input_generator(TEST);
+        let board = Board::from(&platform);
+        let stepped = board.run(&Program::parse("N N E").unwrap(), 1);
+        assert_eq!(stepped.to_string(), platform.clone().tip_north().tip_east().to_string());
+        for edge in [Direction::North, Direction::West, Direction::South, Direction::East] {
+            assert_eq!(stepped.load(edge), rendered_load(&stepped, edge));
+        }
+
+        // The example settles into a loop of 7 after 3 spins
+        let orbit = board.orbit(&Program::spin());
+        assert_eq!((orbit.start, orbit.period()), (3, 7));
+        assert_eq!(orbit.pre_period().count(), 3);
+        assert_eq!(orbit.state(1_000_000_000).load(Direction::North), 64);
+        assert_eq!(orbit.state(2).to_string(), platform.clone().cycles(2).to_string());
+        let csv = orbit.to_csv();
+        assert_eq!(csv.lines().count(), 11);
+        assert!(csv.lines().nth(4).unwrap().starts_with("3,cycle,"));
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bitboard_bench() {
+        let platform = input_generator(&random_platform(&mut StdRng::seed_from_u64(14), 100, 100));
+        let timer = Instant::now();
+        let load = solve_part2(&platform);
+        let bitboard = timer.elapsed();
+        let timer = Instant::now();
+        let expected = solve_part2_brent(&platform);
+        let brent = timer.elapsed();
+        assert_eq!(load, expected);
+        println!("bitboard: {:?}, brent: {:?}", bitboard, brent);
+    }
+}